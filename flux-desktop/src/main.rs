@@ -2,12 +2,15 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use image::RgbaImage;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::sync::mpsc;
 
 use winit::{
     application::ApplicationHandler,
-    event::{ElementState, KeyEvent, WindowEvent},
+    dpi::LogicalPosition,
+    event::{ElementState, KeyEvent, MouseButton, Touch, TouchPhase, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowId},
@@ -27,6 +30,21 @@ struct App {
     _settings: Arc<Settings>,
 
     color_image: Arc<Mutex<Option<RgbaImage>>>,
+
+    // Pointers currently stirring the fluid, keyed by touch id.
+    pointers: HashMap<u64, Pointer>,
+    cursor_position: Option<LogicalPosition<f32>>,
+}
+
+// The radius of the splat left by a dragged pointer, in logical pixels.
+const POINTER_RADIUS: f32 = 40.0;
+
+// The mouse shares the pointer map with touches.
+const MOUSE_POINTER_ID: u64 = u64::MAX;
+
+struct Pointer {
+    position: LogicalPosition<f32>,
+    time: Instant,
 }
 
 enum Msg {
@@ -50,7 +68,10 @@ impl App {
         let tx = self.tx.clone();
         let color_image = Arc::clone(&self.color_image);
         self.runtime.spawn(async move {
-            match flux::render::color::Context::decode_color_texture(&encoded_bytes) {
+            match flux::render::color::Context::decode_color_texture(
+                &encoded_bytes,
+                &flux::settings::ImageOptions::default(),
+            ) {
                 Ok(image) => {
                     {
                         let mut boop = color_image.lock().unwrap();
@@ -65,6 +86,41 @@ impl App {
         });
         log::debug!("Spawned image decoding task");
     }
+
+    fn pointer_down(&mut self, id: u64, position: LogicalPosition<f32>) {
+        self.pointers.insert(
+            id,
+            Pointer {
+                position,
+                time: Instant::now(),
+            },
+        );
+    }
+
+    // Push the fluid along with the pointer's velocity since its last event.
+    fn pointer_moved(&mut self, id: u64, position: LogicalPosition<f32>) {
+        let Some(pointer) = self.pointers.get_mut(&id) else {
+            return;
+        };
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(pointer.time).as_secs_f32();
+        if elapsed > 0.0 {
+            let velocity = [
+                (position.x - pointer.position.x) / elapsed,
+                (position.y - pointer.position.y) / elapsed,
+            ];
+            self.flux
+                .add_impulse([position.x, position.y], velocity, POINTER_RADIUS);
+        }
+
+        pointer.position = position;
+        pointer.time = now;
+    }
+
+    fn pointer_up(&mut self, id: u64) {
+        self.pointers.remove(&id);
+    }
 }
 
 struct GpuState {
@@ -88,10 +144,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(1)
         .enable_all()
-        .build()
-        .unwrap();
+        .build()?;
 
-    let event_loop = EventLoop::new().unwrap();
+    let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
 
     let mut flux_app = FluxApp {
@@ -106,12 +161,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-impl ApplicationHandler for FluxApp {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        if self.window.is_some() {
-            return;
-        }
-
+impl FluxApp {
+    fn init(&mut self, event_loop: &ActiveEventLoop) -> Result<(), Box<dyn std::error::Error>> {
         let logical_size = winit::dpi::LogicalSize::new(1280, 800);
 
         #[cfg(target_os = "macos")]
@@ -131,18 +182,17 @@ impl ApplicationHandler for FluxApp {
             .with_resizable(true)
             .with_inner_size(logical_size);
 
-        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        let window = Arc::new(event_loop.create_window(window_attributes)?);
 
         let wgpu_instance = wgpu::Instance::default();
-        let window_surface = wgpu_instance.create_surface(window.clone()).unwrap();
+        let window_surface = wgpu_instance.create_surface(window.clone())?;
         let adapter =
             pollster::block_on(wgpu_instance.request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
                 force_fallback_adapter: false,
                 compatible_surface: Some(&window_surface),
                 apply_limit_buckets: false,
-            }))
-            .expect("Failed to find an appropriate adapter");
+            }))?;
 
         let limits = wgpu::Limits::default().using_resolution(adapter.limits());
 
@@ -166,12 +216,11 @@ impl ApplicationHandler for FluxApp {
                 memory_hints: wgpu::MemoryHints::Performance,
                 trace: wgpu::Trace::Off,
                 experimental_features: wgpu::ExperimentalFeatures::disabled(),
-            }))
-            .expect("Failed to create device");
+            }))?;
 
         let swapchain_capabilities = window_surface.get_capabilities(&adapter);
         let surface_output = get_preferred_surface_output(&swapchain_capabilities)
-            .expect("Surface does not support a renderer-compatible color space");
+            .ok_or("Surface does not support a renderer-compatible color space")?;
         let display_hdr_info = window_surface.display_hdr_info(&adapter);
         log::info!(
             "Surface output: format={:?}, color_space={:?}, hdr={}, headroom={:?}",
@@ -212,8 +261,7 @@ impl ApplicationHandler for FluxApp {
             physical_size.height,
             caps,
             &Arc::clone(&settings),
-        )
-        .unwrap();
+        )?;
 
         window.set_visible(true);
 
@@ -222,9 +270,7 @@ impl ApplicationHandler for FluxApp {
         // Take the runtime out temporarily to create the App
         let runtime = std::mem::replace(
             &mut self.runtime,
-            tokio::runtime::Builder::new_current_thread()
-                .build()
-                .unwrap(),
+            tokio::runtime::Builder::new_current_thread().build()?,
         );
 
         self.app = Some(App {
@@ -234,6 +280,8 @@ impl ApplicationHandler for FluxApp {
             flux,
             _settings: settings,
             color_image: Arc::new(Mutex::new(None)),
+            pointers: HashMap::new(),
+            cursor_position: None,
         });
 
         self.gpu = Some(GpuState {
@@ -245,6 +293,21 @@ impl ApplicationHandler for FluxApp {
 
         self.window = Some(window);
         self.start = std::time::Instant::now();
+
+        Ok(())
+    }
+}
+
+impl ApplicationHandler for FluxApp {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+
+        if let Err(err) = self.init(event_loop) {
+            log::error!("Failed to start Flux: {}", err);
+            event_loop.exit();
+        }
     }
 
     fn window_event(
@@ -276,10 +339,44 @@ impl ApplicationHandler for FluxApp {
                     },
                 ..
             } => event_loop.exit(),
-            WindowEvent::DroppedFile(path) => {
-                let bytes = std::fs::read(path).unwrap();
-                app.decode_image(bytes);
-                window.request_redraw();
+            WindowEvent::DroppedFile(path) => match std::fs::read(&path) {
+                Ok(bytes) => {
+                    app.decode_image(bytes);
+                    window.request_redraw();
+                }
+                Err(source) => log::error!("{}", flux::Error::ReadImage { path, source }),
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = position.to_logical(window.scale_factor());
+                app.cursor_position = Some(position);
+                app.pointer_moved(MOUSE_POINTER_ID, position);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                app.cursor_position = None;
+                app.pointer_up(MOUSE_POINTER_ID);
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => match (state, app.cursor_position) {
+                (ElementState::Pressed, Some(position)) => {
+                    app.pointer_down(MOUSE_POINTER_ID, position)
+                }
+                _ => app.pointer_up(MOUSE_POINTER_ID),
+            },
+            WindowEvent::Touch(Touch {
+                id,
+                phase,
+                location,
+                ..
+            }) => {
+                let position = location.to_logical(window.scale_factor());
+                match phase {
+                    TouchPhase::Started => app.pointer_down(id, position),
+                    TouchPhase::Moved => app.pointer_moved(id, position),
+                    TouchPhase::Ended | TouchPhase::Cancelled => app.pointer_up(id),
+                }
             }
             WindowEvent::Resized(new_size) => {
                 gpu.config.width = new_size.width.max(1);
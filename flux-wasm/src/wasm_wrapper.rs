@@ -1,5 +1,6 @@
 use flux::{self, settings};
 use gloo_utils::format::JsValueSerdeExt;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use wasm_bindgen::prelude::*;
@@ -16,15 +17,38 @@ pub struct Flux {
     logical_height: u32,
     pixel_ratio: f64,
     instance: flux::Flux,
+    pointers: HashMap<i32, Pointer>,
+}
+
+// The radius of the splat left by a dragged pointer, in CSS pixels.
+const POINTER_RADIUS: f32 = 40.0;
+
+struct Pointer {
+    position: [f32; 2],
+    timestamp: f64,
 }
 
 #[wasm_bindgen]
 impl Flux {
     #[wasm_bindgen(setter)]
     pub fn set_settings(&mut self, settings_object: &JsValue) {
-        let settings: settings::Settings = settings_object.into_serde().unwrap();
-        self.instance
-            .update(&self.device, &self.queue, &Arc::new(settings));
+        match parse_settings(settings_object) {
+            Ok(settings) => {
+                if let Err(err) =
+                    self.instance
+                        .update(&self.device, &self.queue, &Arc::new(settings))
+                {
+                    log::error!("{}", err);
+                }
+            }
+            Err(err) => log::error!("{}", err),
+        }
+    }
+
+    /// Starts a new scene, optionally from a seed. See `flux::Flux::reset`.
+    #[wasm_bindgen]
+    pub fn reset(&mut self, seed: Option<String>) {
+        self.instance.reset(&self.queue, seed);
     }
 
     #[wasm_bindgen]
@@ -104,17 +128,14 @@ impl Flux {
 
         let canvas = Canvas::new(html_canvas.clone());
 
-        let settings = match settings_object.into_serde() {
-            Ok(settings) => Arc::new(settings),
-            Err(msg) => return Err(JsValue::from_str(&msg.to_string())),
-        };
+        let settings = Arc::new(parse_settings(settings_object).map_err(to_js_error)?);
 
         let mut instance_desc = wgpu::InstanceDescriptor::new_without_display_handle();
         instance_desc.backends = wgpu::Backends::BROWSER_WEBGPU;
         let wgpu_instance = wgpu::Instance::new(instance_desc);
         let window_surface = wgpu_instance
             .create_surface(wgpu::SurfaceTarget::Canvas(html_canvas))
-            .expect("Failed to create surface");
+            .map_err(to_js_error)?;
         let adapter = wgpu_instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
@@ -123,7 +144,7 @@ impl Flux {
                 apply_limit_buckets: false,
             })
             .await
-            .expect("Failed to find an appropiate adapter");
+            .map_err(to_js_error)?;
 
         log::debug!("{:?}\n{:?}", adapter.get_info(), adapter.features(),);
 
@@ -152,7 +173,7 @@ impl Flux {
                 experimental_features: wgpu::ExperimentalFeatures::disabled(),
             })
             .await
-            .expect("Failed to create device");
+            .map_err(to_js_error)?;
 
         let swapchain_capabilities = window_surface.get_capabilities(&adapter);
         let display_hdr_info = window_surface.display_hdr_info(&adapter);
@@ -210,7 +231,7 @@ impl Flux {
             caps,
             &settings,
         )
-        .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        .map_err(to_js_error)?;
 
         Ok(Self {
             instance: flux,
@@ -221,9 +242,46 @@ impl Flux {
             logical_width,
             logical_height,
             pixel_ratio,
+            pointers: HashMap::new(),
         })
     }
 
+    /// Start stirring the fluid with a pointer. Coordinates are in CSS pixels
+    /// relative to the canvas, and `timestamp` is the event time in
+    /// milliseconds.
+    pub fn pointer_down(&mut self, pointer_id: i32, x: f32, y: f32, timestamp: f64) {
+        self.pointers.insert(
+            pointer_id,
+            Pointer {
+                position: [x, y],
+                timestamp,
+            },
+        );
+    }
+
+    /// Push the fluid along with the pointer's velocity since its last event.
+    pub fn pointer_move(&mut self, pointer_id: i32, x: f32, y: f32, timestamp: f64) {
+        let Some(pointer) = self.pointers.get_mut(&pointer_id) else {
+            return;
+        };
+
+        let elapsed = ((timestamp - pointer.timestamp) / 1000.0) as f32;
+        if elapsed > 0.0 {
+            let velocity = [
+                (x - pointer.position[0]) / elapsed,
+                (y - pointer.position[1]) / elapsed,
+            ];
+            self.instance.add_impulse([x, y], velocity, POINTER_RADIUS);
+        }
+
+        pointer.position = [x, y];
+        pointer.timestamp = timestamp;
+    }
+
+    pub fn pointer_up(&mut self, pointer_id: i32) {
+        self.pointers.remove(&pointer_id);
+    }
+
     pub fn animate(&mut self, timestamp: f64) {
         let mut retried_outdated_surface = false;
         let frame = loop {
@@ -371,3 +429,16 @@ fn physical_from_logical_size(
         (pixel_ratio * f64::from(logical_height)) as u32,
     )
 }
+
+// Settings from the page may have been saved by an older version of Flux.
+fn parse_settings(settings_object: &JsValue) -> Result<settings::Settings, flux::Error> {
+    let value = settings_object
+        .into_serde::<serde_json::Value>()
+        .map_err(|err| flux::Error::ParseSettings(err.to_string()))?;
+    settings::Settings::from_value(value)
+}
+
+// Surface errors to JS as proper `Error`s, so the page can show the message.
+fn to_js_error<E: std::fmt::Display>(err: E) -> JsValue {
+    js_sys::Error::new(&err.to_string()).into()
+}
@@ -0,0 +1,141 @@
+//! Compares the pressure solvers by how much each reduces the pressure
+//! residual per millisecond of GPU time.
+//!
+//! Each configuration runs the full fluid step on an offscreen instance. The
+//! cost of the solver is the step time less that of a step with no pressure
+//! iterations at all, and its progress is the number of orders of magnitude
+//! it takes off the residual in a single step.
+//!
+//! Run with `cargo bench -p flux --bench pressure_solver`. Needs a GPU.
+
+use flux::settings::{PressureSolver, Settings};
+use flux::{BackendCaps, Flux};
+use std::sync::Arc;
+use std::time::Instant;
+
+// A 16:9 window, so the largest fluid size covers a 4K screen at a fluid
+// cell per 4 pixels.
+const WIDTH: u32 = 1600;
+const HEIGHT: u32 = 900;
+const FLUID_SIZES: [u32; 3] = [128, 256, 512];
+
+const WARMUP_STEPS: u32 = 20;
+const TIMED_STEPS: u32 = 60;
+
+const SOLVERS: [(&str, PressureSolver, u32); 5] = [
+    ("jacobi x19", PressureSolver::Jacobi, 19),
+    ("jacobi x100", PressureSolver::Jacobi, 100),
+    ("multigrid x1", PressureSolver::Multigrid { cycles: 1 }, 0),
+    ("multigrid x2", PressureSolver::Multigrid { cycles: 2 }, 0),
+    ("multigrid x4", PressureSolver::Multigrid { cycles: 4 }, 0),
+];
+
+struct Measurement {
+    ms_per_step: f64,
+    residual: f32,
+}
+
+fn main() {
+    let Some((device, queue, caps)) = pollster::block_on(request_device()) else {
+        eprintln!("No GPU adapter available; skipping the pressure solver benchmark.");
+        return;
+    };
+
+    println!(
+        "{:>11} {:<13} {:>10} {:>10} {:>14}",
+        "fluid size", "solver", "solver ms", "residual", "orders per ms"
+    );
+
+    for fluid_size in FLUID_SIZES {
+        let baseline = measure(&device, &queue, caps, fluid_size, PressureSolver::Jacobi, 0);
+
+        for (name, solver, iterations) in SOLVERS {
+            let measurement = measure(&device, &queue, caps, fluid_size, solver, iterations);
+            let solver_ms = (measurement.ms_per_step - baseline.ms_per_step).max(1e-3);
+            let orders = -f64::from(measurement.residual).log10();
+
+            println!(
+                "{:>11} {:<13} {:>10.3} {:>10.2e} {:>14.2}",
+                fluid_size,
+                name,
+                solver_ms,
+                measurement.residual,
+                orders / solver_ms,
+            );
+        }
+    }
+}
+
+fn measure(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    caps: BackendCaps,
+    fluid_size: u32,
+    pressure_solver: PressureSolver,
+    pressure_iterations: u32,
+) -> Measurement {
+    let settings = Arc::new(Settings {
+        fluid_size,
+        pressure_solver,
+        pressure_iterations,
+        ..Default::default()
+    });
+    let mut flux = Flux::new_offscreen(device, queue, WIDTH, HEIGHT, caps, &settings)
+        .expect("failed to create an offscreen instance");
+
+    // Let the flow develop, so there is something to solve.
+    flux.step(device, queue, WARMUP_STEPS, settings.fluid_timestep)
+        .expect("failed to step the simulation");
+    wait(device);
+
+    let start = Instant::now();
+    flux.step(device, queue, TIMED_STEPS, settings.fluid_timestep)
+        .expect("failed to step the simulation");
+    wait(device);
+    let elapsed = start.elapsed();
+
+    Measurement {
+        ms_per_step: elapsed.as_secs_f64() * 1000.0 / f64::from(TIMED_STEPS),
+        residual: flux
+            .pressure_residual(device, queue)
+            .expect("failed to read back the pressure residual"),
+    }
+}
+
+fn wait(device: &wgpu::Device) {
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .expect("failed to wait for the GPU");
+}
+
+async fn request_device() -> Option<(wgpu::Device, wgpu::Queue, BackendCaps)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })
+        .await
+        .ok()?;
+
+    let caps = BackendCaps {
+        float32_filterable: adapter
+            .features()
+            .contains(wgpu::Features::FLOAT32_FILTERABLE),
+    };
+    let mut features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+    if caps.float32_filterable {
+        features |= wgpu::Features::FLOAT32_FILTERABLE;
+    }
+
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            required_features: features,
+            required_limits: adapter.limits(),
+            ..Default::default()
+        })
+        .await
+        .ok()?;
+
+    Some((device, queue, caps))
+}
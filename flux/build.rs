@@ -0,0 +1,42 @@
+use std::{env, error::Error, fs, path::Path};
+
+// Expand `#include "file.inc.wgsl"` lines in the shaders at build time.
+//
+// WGSL has no way of sharing code between modules, so helpers used by several
+// shaders live in `.inc.wgsl` files and are pasted in here. The expanded
+// shaders are written to `$OUT_DIR/shader`.
+fn main() -> Result<(), Box<dyn Error>> {
+    let out_dir = env::var_os("OUT_DIR").expect("missing output directory");
+    let out_dir = Path::new(&out_dir).join("shader");
+    fs::create_dir_all(&out_dir)?;
+
+    println!("cargo:rerun-if-changed=shader");
+
+    for entry in fs::read_dir("shader")? {
+        let path = entry?.path();
+        let file_name = path.file_name().expect("missing file name");
+        if file_name.to_string_lossy().ends_with(".inc.wgsl") {
+            continue;
+        }
+
+        let mut expanded = String::new();
+        for line in fs::read_to_string(&path)?.lines() {
+            match line
+                .strip_prefix("#include \"")
+                .and_then(|rest| rest.strip_suffix('"'))
+            {
+                Some(include) => {
+                    expanded.push_str(&fs::read_to_string(Path::new("shader").join(include))?)
+                }
+                None => {
+                    expanded.push_str(line);
+                    expanded.push('\n');
+                }
+            }
+        }
+
+        fs::write(out_dir.join(file_name), expanded)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+// include fluid.inc
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var forward_advected_texture: texture_2d<f32>;
+@group(1) @binding(1) var reverse_advected_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var dye_texture: texture_2d<f32>;
+@group(2) @binding(1) var out_dye_texture: texture_storage_2d<rgba16float, write>;
+
+@group(3) @binding(0) var velocity_texture: texture_2d<f32>;
+
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
+fn load_dye(cell: vec2<i32>) -> vec4<f32> {
+  let size = vec2<i32>(textureDimensions(dye_texture));
+  return textureLoad(dye_texture, edge_cell(cell, size), 0);
+}
+
+// The MacCormack correction, clamped to the dye around the departure point
+// so it can't overshoot.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  if (is_solid(vec2<i32>(global_id.xy))) {
+    textureStore(out_dye_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
+  let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
+
+  let advected_position = (vec2<f32>(global_id.xy) + 0.5) - uniforms.timestep * velocity;
+  let corner = vec2<i32>(floor(advected_position - 0.5));
+  let a = load_dye(corner);
+  let b = load_dye(corner + vec2<i32>(1, 0));
+  let c = load_dye(corner + vec2<i32>(0, 1));
+  let d = load_dye(corner + vec2<i32>(1, 1));
+
+  let min_dye = min(min(a, b), min(c, d));
+  let max_dye = max(max(a, b), max(c, d));
+
+  let dye = textureLoad(dye_texture, global_id.xy, 0);
+  let forward = textureLoad(forward_advected_texture, global_id.xy, 0);
+  let reverse = textureLoad(reverse_advected_texture, global_id.xy, 0);
+
+  let adjusted_dye = clamp(forward + 0.5 * (dye - reverse), min_dye, max_dye);
+  let decay = 1.0 + uniforms.dye_dissipation * uniforms.timestep;
+  textureStore(out_dye_texture, global_id.xy, adjusted_dye / decay);
+}
@@ -6,11 +6,15 @@ struct FluidUniforms {
   r_beta: f32,
   center_factor: f32,
   stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
 }
 
 @group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
 @group(0) @binding(1) var linear_sampler: sampler;
 @group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
 
 @group(1) @binding(0) var out_texture: texture_storage_2d<rgba16float, write>;
 
@@ -23,11 +27,20 @@ struct Dir {
   direction: f32,
 }
 
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
 @compute
 @workgroup_size(16, 16, 1)
 fn main(
   @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
+  // Nothing moves inside an obstacle.
+  if (is_solid(vec2<i32>(global_id.xy))) {
+    textureStore(out_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
   let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
 
   // Note, that, by multiplying by dx, we’ve “incorrectly” scaled our coordinate system.
@@ -37,6 +50,9 @@ fn main(
 
   let advected_position = ((sample_position + 0.5) - direction.direction * uniforms.timestep * velocity) / size;
   let decay = 1.0 + uniforms.dissipation * uniforms.timestep;
-  let new_velocity = textureSampleLevel(velocity_texture, linear_sampler, advected_position, 0.0).xy / decay;
+  let advected_velocity = textureSampleLevel(velocity_texture, linear_sampler, advected_position, 0.0).xy / decay;
+
+  // The walls hold back whatever flow they stop, as in `subtract_gradient.comp.wgsl`.
+  let new_velocity = advected_velocity * wall_mask(vec2<i32>(global_id.xy), vec2<i32>(size));
   textureStore(out_texture, global_id.xy, vec4<f32>(new_velocity, 0.0, 0.0));
 }
@@ -0,0 +1,64 @@
+// include fluid.inc
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var corrected_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_velocity_texture: texture_storage_2d<rgba16float, write>;
+
+@group(2) @binding(0) var velocity_texture: texture_2d<f32>;
+
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
+fn velocity_at(cell: vec2<i32>) -> vec2<f32> {
+  let size = vec2<i32>(textureDimensions(velocity_texture));
+  return textureLoad(velocity_texture, edge_cell(cell, size), 0).xy;
+}
+
+// BFECC step 4. Advects the corrected velocity from `correct_bfecc.comp.wgsl`
+// along the original one. The result is clamped to the velocities around the
+// departure point, so the correction can't overshoot.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  // Nothing moves inside an obstacle.
+  if (is_solid(vec2<i32>(global_id.xy))) {
+    textureStore(out_velocity_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
+  let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
+
+  let size = vec2<f32>(textureDimensions(velocity_texture));
+  let advected_position = (vec2<f32>(global_id.xy) + 0.5) - uniforms.timestep * velocity;
+  let decay = 1.0 + uniforms.dissipation * uniforms.timestep;
+  let advected_velocity = textureSampleLevel(corrected_texture, linear_sampler, advected_position / size, 0.0).xy / decay;
+
+  // The texels the linear sample interpolates between.
+  let cell = vec2<i32>(floor(advected_position - 0.5));
+  let a = velocity_at(cell);
+  let b = velocity_at(cell + vec2<i32>(1, 0));
+  let c = velocity_at(cell + vec2<i32>(0, 1));
+  let d = velocity_at(cell + vec2<i32>(1, 1));
+  let min_velocity = min(min(a, b), min(c, d)) / decay;
+  let max_velocity = max(max(a, b), max(c, d)) / decay;
+
+  let new_velocity = clamp(advected_velocity, min_velocity, max_velocity);
+  textureStore(out_velocity_texture, global_id.xy, vec4<f32>(new_velocity, 0.0, 0.0));
+}
@@ -0,0 +1,52 @@
+// include fluid.inc
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  dye_dissipation: f32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var dye_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_dye_texture: texture_storage_2d<rgba16float, write>;
+
+@group(2) @binding(0) var<uniform> direction: Dir;
+
+@group(3) @binding(0) var velocity_texture: texture_2d<f32>;
+
+struct Dir {
+  padding: vec3<u32>,
+  direction: f32,
+}
+
+#include "fluid.inc.wgsl"
+
+// Carry the dye along the velocity field. Like `advect.comp.wgsl`, but the
+// advected quantity lives in its own texture. Dissipation is applied once, in
+// `adjust_dye_advection.comp.wgsl`.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  if (is_solid(vec2<i32>(global_id.xy))) {
+    textureStore(out_dye_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
+  let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
+
+  let size = vec2<f32>(textureDimensions(dye_texture));
+  let sample_position = vec2<f32>(global_id.xy);
+
+  let advected_position = ((sample_position + 0.5) - direction.direction * uniforms.timestep * velocity) / size;
+  let new_dye = textureSampleLevel(dye_texture, linear_sampler, advected_position, 0.0);
+  textureStore(out_dye_texture, global_id.xy, new_dye);
+}
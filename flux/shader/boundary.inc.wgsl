@@ -0,0 +1,42 @@
+// Shared by the fluid shaders that read `uniforms.boundary`. Pasted in by
+// `build.rs` wherever a shader has `#include "boundary.inc.wgsl"`.
+
+// Match `settings::Boundary`.
+const BOUNDARY_FREE_SLIP: u32 = 0u;
+const BOUNDARY_NO_SLIP: u32 = 1u;
+const BOUNDARY_PERIODIC: u32 = 2u;
+
+// Cells past the edge of the domain wrap around to the other side if it's
+// periodic, and clamp to the edge otherwise.
+fn edge_cell(cell: vec2<i32>, size: vec2<i32>) -> vec2<i32> {
+  if (uniforms.boundary == BOUNDARY_PERIODIC) {
+    return (cell % size + size) % size;
+  }
+  return clamp(cell, vec2<i32>(0), size - 1);
+}
+
+// Whether `cell` lies in the wall past the edge of the domain. A periodic
+// domain has no walls.
+fn is_wall(cell: vec2<i32>, size: vec2<i32>) -> bool {
+  if (uniforms.boundary == BOUNDARY_PERIODIC) {
+    return false;
+  }
+  return any(cell < vec2<i32>(0)) || any(cell >= size);
+}
+
+// Which components of the velocity at `cell` the walls let through. Free-slip
+// walls only stop the flow across them, so the fluid can still slide along.
+// No-slip walls stop it altogether.
+fn wall_mask(cell: vec2<i32>, size: vec2<i32>) -> vec2<f32> {
+  if (uniforms.boundary == BOUNDARY_PERIODIC) {
+    return vec2<f32>(1.0);
+  }
+  let on_edge = vec2<bool>(
+    cell.x == 0 || cell.x == size.x - 1,
+    cell.y == 0 || cell.y == size.y - 1,
+  );
+  if (uniforms.boundary == BOUNDARY_NO_SLIP && any(on_edge)) {
+    return vec2<f32>(0.0);
+  }
+  return select(vec2<f32>(1.0), vec2<f32>(0.0), on_edge);
+}
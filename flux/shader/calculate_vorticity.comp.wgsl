@@ -0,0 +1,49 @@
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var out_vorticity_texture: texture_storage_2d<r32float, write>;
+
+@group(2) @binding(0) var velocity_texture: texture_2d<f32>;
+
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
+fn velocity_at(cell: vec2<i32>) -> vec2<f32> {
+  let size = vec2<i32>(textureDimensions(velocity_texture));
+  return textureLoad(velocity_texture, edge_cell(cell, size), 0).xy;
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let cell = vec2<i32>(global_id.xy);
+  if (is_solid(cell)) {
+    textureStore(out_vorticity_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
+  let l = velocity_at(cell + vec2<i32>(-1, 0)).y;
+  let r = velocity_at(cell + vec2<i32>(1, 0)).y;
+  let b = velocity_at(cell + vec2<i32>(0, -1)).x;
+  let t = velocity_at(cell + vec2<i32>(0, 1)).x;
+
+  let curl = 0.5 * ((r - l) - (t - b));
+
+  textureStore(out_vorticity_texture, global_id.xy, vec4<f32>(curl, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,48 @@
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var pressure_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_pressure_texture: texture_storage_2d<r32float, write>;
+
+const WORKGROUP_SIZE: u32 = 256u;
+
+var<workgroup> sums: array<vec2<f32>, WORKGROUP_SIZE>;
+
+// Subtracts the mean pressure over the open cells.
+//
+// With only Neumann boundaries, the pressure is defined up to a constant, and
+// the coarse multigrid corrections drift further from zero with every sweep.
+// Run as a single workgroup on the coarsest level.
+@compute
+@workgroup_size(256, 1, 1)
+fn main(
+  @builtin(local_invocation_index) index: u32,
+) {
+  let size = textureDimensions(pressure_texture);
+  let cell_count = size.x * size.y;
+
+  var sum = vec2<f32>(0.0);
+  for (var i = index; i < cell_count; i += WORKGROUP_SIZE) {
+    let cell = vec2<u32>(i % size.x, i / size.x);
+    if (textureLoad(obstacle_texture, cell, 0).x <= 0.5) {
+      sum += vec2<f32>(textureLoad(pressure_texture, cell, 0).x, 1.0);
+    }
+  }
+  sums[index] = sum;
+
+  for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+    workgroupBarrier();
+    if (index < stride) {
+      sums[index] += sums[index + stride];
+    }
+  }
+  workgroupBarrier();
+
+  let total = sums[0];
+  let mean = total.x / max(total.y, 1.0);
+
+  for (var i = index; i < cell_count; i += WORKGROUP_SIZE) {
+    let cell = vec2<u32>(i % size.x, i / size.x);
+    let pressure = textureLoad(pressure_texture, cell, 0).x;
+    textureStore(out_pressure_texture, cell, vec4<f32>(pressure - mean, 0.0, 0.0, 0.0));
+  }
+}
@@ -0,0 +1,58 @@
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var vorticity_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var velocity_texture: texture_2d<f32>;
+@group(2) @binding(1) var out_velocity_texture: texture_storage_2d<rgba16float, write>;
+
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
+fn curl_at(cell: vec2<i32>) -> f32 {
+  let size = vec2<i32>(textureDimensions(vorticity_texture));
+  return textureLoad(vorticity_texture, edge_cell(cell, size), 0).x;
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let cell = vec2<i32>(global_id.xy);
+  let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
+  if (is_solid(cell)) {
+    textureStore(out_velocity_texture, global_id.xy, vec4<f32>(velocity, 0.0, 0.0));
+    return;
+  }
+
+  let curl = curl_at(cell);
+  let l = abs(curl_at(cell + vec2<i32>(-1, 0)));
+  let r = abs(curl_at(cell + vec2<i32>(1, 0)));
+  let b = abs(curl_at(cell + vec2<i32>(0, -1)));
+  let t = abs(curl_at(cell + vec2<i32>(0, 1)));
+
+  // Push along the isolines of |curl|, spinning each swirl up around its
+  // centre. See Fedkiw et al., “Visual Simulation of Smoke”.
+  let gradient = 0.5 * vec2<f32>(r - l, t - b);
+  let normal = gradient / (length(gradient) + 1e-5);
+  let force = uniforms.vorticity * curl * vec2<f32>(normal.y, -normal.x);
+
+  let new_velocity = velocity + uniforms.timestep * force;
+
+  textureStore(out_velocity_texture, global_id.xy, vec4<f32>(new_velocity, 0.0, 0.0));
+}
@@ -0,0 +1,41 @@
+// include fluid.inc
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var reverse_advected_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_corrected_texture: texture_storage_2d<rgba16float, write>;
+
+@group(2) @binding(0) var velocity_texture: texture_2d<f32>;
+
+#include "fluid.inc.wgsl"
+
+// BFECC step 3. A forward and a reverse trace should land back on the
+// velocity we started with; half the difference estimates the error of one
+// trace, which is removed from the velocity before advecting it again.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  if (is_solid(vec2<i32>(global_id.xy))) {
+    textureStore(out_corrected_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
+  let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
+  let reverse = textureLoad(reverse_advected_texture, global_id.xy, 0).xy;
+
+  let corrected_velocity = velocity + 0.5 * (velocity - reverse);
+  textureStore(out_corrected_texture, global_id.xy, vec4<f32>(corrected_velocity, 0.0, 0.0));
+}
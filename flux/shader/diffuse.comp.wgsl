@@ -11,15 +11,24 @@ struct FluidUniforms {
 @group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
 @group(0) @binding(1) var linear_sampler: sampler;
 @group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
 
 @group(1) @binding(0) var velocity_texture: texture_2d<f32>;
 @group(1) @binding(1) var out_texture: texture_storage_2d<rgba16float, write>;
 
+#include "fluid.inc.wgsl"
+
 @compute
 @workgroup_size(16, 16, 1)
 fn main(
   @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
+  // Nothing moves inside an obstacle.
+  if (is_solid(vec2<i32>(global_id.xy))) {
+    textureStore(out_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
   let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
 
   let size = textureDimensions(velocity_texture, 0);
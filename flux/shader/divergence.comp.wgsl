@@ -1,21 +1,59 @@
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
+}
+
 @group(0) @binding(0) var nearest_sampler: sampler;
 @group(0) @binding(1) var out_divergence_texture: texture_storage_2d<r32float, write>;
+@group(0) @binding(2) var obstacle_texture: texture_2d<f32>;
+@group(0) @binding(3) var<uniform> uniforms: FluidUniforms;
 
 @group(1) @binding(0) var velocity_texture: texture_2d<f32>;
 @group(1) @binding(1) var out_velocity_texture: texture_storage_2d<rgba16float, write>;
 
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
 @compute
 @workgroup_size(16, 16, 1)
 fn main(
   @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
+  let cell = vec2<i32>(global_id.xy);
+  if (is_solid(cell)) {
+    textureStore(out_divergence_texture, global_id.xy, vec4<f32>(0.0));
+    return;
+  }
+
   let size = textureDimensions(velocity_texture);
   let sample_position = vec2<f32>(global_id.xy) / vec2<f32>(size);
 
-  let l = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(-1, 0)).x;
-  let r = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(1, 0)).x;
-  let t = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(0, 1)).y;
-  let b = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(0, -1)).y;
+  var l = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(-1, 0)).x;
+  var r = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(1, 0)).x;
+  var t = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(0, 1)).y;
+  var b = textureSampleLevel(velocity_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(0, -1)).y;
+
+  // Obstacles and walls don't move, so no flow crosses into them.
+  let domain = vec2<i32>(size);
+  if (is_solid(cell + vec2<i32>(-1, 0)) || is_wall(cell + vec2<i32>(-1, 0), domain)) {
+    l = 0.0;
+  }
+  if (is_solid(cell + vec2<i32>(1, 0)) || is_wall(cell + vec2<i32>(1, 0), domain)) {
+    r = 0.0;
+  }
+  if (is_solid(cell + vec2<i32>(0, 1)) || is_wall(cell + vec2<i32>(0, 1), domain)) {
+    t = 0.0;
+  }
+  if (is_solid(cell + vec2<i32>(0, -1)) || is_wall(cell + vec2<i32>(0, -1), domain)) {
+    b = 0.0;
+  }
 
   let new_divergence = 0.5 * ((r - l) + (t - b));
 
@@ -0,0 +1,50 @@
+struct LineUniforms {
+  aspect: f32,
+  zoom: f32,
+  line_width: f32,
+  line_length: f32,
+  line_begin_offset: f32,
+  line_variance: f32,
+  line_noise_scale: vec2<f32>,
+  line_noise_offset_1: f32,
+  line_noise_offset_2: f32,
+  line_noise_blend_factor: f32,
+  color_mode: u32,
+  delta_time: f32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: LineUniforms;
+@group(1) @binding(0) var<uniform> view_matrix: mat4x4<f32>;
+
+@group(2) @binding(0) var dye_texture: texture_2d<f32>;
+@group(2) @binding(1) var dye_sampler: sampler;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+}
+
+// Two triangles covering the fluid domain, placed the same way as the line
+// basepoints in `line.wgsl`.
+@vertex
+fn main_vs(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  var corners = array<vec2<f32>, 6>(
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(1.0, 1.0),
+  );
+  let uv = corners[vertex_index];
+  let point = uniforms.zoom * (uv * 2.0 - 1.0);
+
+  return VertexOutput(view_matrix * vec4<f32>(point, 0.0, 1.0), uv);
+}
+
+// The dye is premultiplied, so draw it straight over black.
+@fragment
+fn main_fs(fs_input: VertexOutput) -> @location(0) vec4<f32> {
+  let dye = textureSample(dye_texture, dye_sampler, fs_input.uv);
+  return vec4<f32>(dye.rgb, 1.0);
+}
@@ -1,5 +1,12 @@
-struct FluidUniforms {
-  timestep: f32,
-  dissipation: f32,
-  texel_size: vec2<f32>,
+// Shared by the fluid shaders, which each bind their own `obstacle_texture`.
+// Pasted in by `build.rs` wherever a shader has `#include "fluid.inc.wgsl"`.
+
+// Cells marked by the obstacle mask. Anything outside the domain is open; the
+// domain edges are handled separately.
+fn is_solid(cell: vec2<i32>) -> bool {
+  let size = vec2<i32>(textureDimensions(obstacle_texture));
+  if (any(cell < vec2<i32>(0)) || any(cell >= size)) {
+    return false;
+  }
+  return textureLoad(obstacle_texture, cell, 0).x > 0.5;
 }
@@ -1,5 +1,6 @@
 struct NoiseUniforms {
   multiplier: f32,
+  periodic: u32,
 }
 
 struct Channel {
@@ -99,13 +100,32 @@ fn make_noise_pair(params: vec3<f32>) -> vec2<f32> {
   return vec2(snoise(params), snoise(params + vec3(8.0, -8.0, 0.0)));
 }
 
+// With a periodic boundary, the noise has to wrap around too. Blend in copies
+// of it shifted by a whole domain, each weighted by how close the texel is to
+// the edge it's shifted from, so that opposite edges meet the same values.
+fn make_tiled_noise_pair(texel_position: vec2<f32>, scale: vec2<f32>, offset: f32) -> vec2<f32> {
+  let position = scale * texel_position;
+  let noise = make_noise_pair(vec3(position, offset));
+  if (uniforms.periodic == 0u) {
+    return noise;
+  }
+
+  let noise_x = make_noise_pair(vec3(position - vec2(scale.x, 0.0), offset));
+  let noise_y = make_noise_pair(vec3(position - vec2(0.0, scale.y), offset));
+  let noise_xy = make_noise_pair(vec3(position - scale, offset));
+  return mix(
+    mix(noise, noise_x, texel_position.x),
+    mix(noise_y, noise_xy, texel_position.x),
+    texel_position.y,
+  );
+}
+
 fn make_noise(texel_position: vec2<f32>, channel: Channel) -> vec2<f32> {
-  let scale = channel.scale * texel_position;
-  let noise1 = make_noise_pair(vec3(scale, channel.offset_1));
+  let noise1 = make_tiled_noise_pair(texel_position, channel.scale, channel.offset_1);
   var noise = noise1;
 
   if (channel.blend_factor > 0.0) {
-    let noise2 = make_noise_pair(vec3(scale, channel.offset_2));
+    let noise2 = make_tiled_noise_pair(texel_position, channel.scale, channel.offset_2);
     noise = mix(noise1, noise2, channel.blend_factor);
   }
 
@@ -0,0 +1,47 @@
+const MAX_DYE_EMITTERS: u32 = 16u;
+
+struct DyeEmitter {
+  color: vec4<f32>,
+  position: vec2<f32>,
+  radius: f32,
+  rate: f32,
+}
+
+struct DyeUniforms {
+  count: u32,
+  aspect: f32,
+  timestep: f32,
+  padding: f32,
+  emitters: array<DyeEmitter, MAX_DYE_EMITTERS>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: DyeUniforms;
+
+@group(1) @binding(0) var dye_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_dye_texture: texture_storage_2d<rgba16float, write>;
+
+// Blend each emitter's color into the dye with a Gaussian falloff. Positions
+// and radii are in texture space, with distances measured in units of the
+// domain height.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(out_dye_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let sample_position = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+
+  var dye = textureLoad(dye_texture, global_id.xy, 0);
+  for (var i = 0u; i < min(uniforms.count, MAX_DYE_EMITTERS); i++) {
+    let emitter = uniforms.emitters[i];
+    let offset = (sample_position - emitter.position) * vec2<f32>(uniforms.aspect, 1.0);
+    let weight = exp(-dot(offset, offset) / (emitter.radius * emitter.radius));
+    dye = mix(dye, emitter.color, saturate(weight * emitter.rate * uniforms.timestep));
+  }
+
+  textureStore(out_dye_texture, global_id.xy, dye);
+}
@@ -0,0 +1,26 @@
+@group(0) @binding(0) var<storage, read_write> max_speed: atomic<u32>;
+
+@group(1) @binding(0) var velocity_texture: texture_2d<f32>;
+
+var<workgroup> workgroup_max_speed: atomic<u32>;
+
+// Finds the fastest speed in the velocity field. Non-negative floats order
+// the same way as their bits, so the speeds are compared as integers. NaN and
+// infinity sort above every finite speed.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+  @builtin(local_invocation_index) local_index: u32,
+) {
+  let size = textureDimensions(velocity_texture);
+  if (all(global_id.xy < size)) {
+    let speed = length(textureLoad(velocity_texture, global_id.xy, 0).xy);
+    atomicMax(&workgroup_max_speed, bitcast<u32>(abs(speed)));
+  }
+
+  workgroupBarrier();
+  if (local_index == 0u) {
+    atomicMax(&max_speed, atomicLoad(&workgroup_max_speed));
+  }
+}
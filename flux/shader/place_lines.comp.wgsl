@@ -20,13 +20,30 @@ struct LineUniforms {
   line_noise_blend_factor: f32,
   color_mode: u32,
   delta_time: f32,
+  velocity_blend: f32,
+  color_space: u32,
 }
 
+// Sorted by position.
+struct GradientStop {
+  color: vec4<f32>,
+  position: f32,
+  interpolation: u32,
+}
+
+const INTERPOLATION_SMOOTH: u32 = 1u;
+const INTERPOLATION_CONSTANT: u32 = 2u;
+
+const COLOR_SPACE_OKLAB: u32 = 1u;
+const COLOR_SPACE_OKLCH: u32 = 2u;
+
 @group(0) @binding(0) var<uniform> uniforms: LineUniforms;
 @group(0) @binding(1) var<storage, read_write> basepoints: array<vec2<f32>>;
 @group(0) @binding(2) var linear_sampler: sampler;
 @group(0) @binding(3) var color_texture_sampler: sampler;
 @group(0) @binding(4) var<storage, read> target_basepoints: array<vec2<f32>>;
+@group(0) @binding(5) var obstacle_texture: texture_2d<f32>;
+@group(0) @binding(6) var previous_velocity_texture: texture_2d<f32>;
 
 // Rate at which each animated basepoint eases toward its target after a grid
 // change. Only a grid_spacing change actually moves the targets (lines glide to
@@ -38,7 +55,8 @@ const BASEPOINT_STIFFNESS: f32 = 6.0;
 @group(1) @binding(1) var<storage, read_write> out_lines: array<Line>;
 
 @group(2) @binding(0) var color_texture: texture_2d<f32>;
-@group(2) @binding(1) var<storage, read> color_buffer: array<vec4<f32>>;
+@group(2) @binding(1) var<storage, read> color_buffer: array<GradientStop>;
+@group(2) @binding(2) var dye_texture: texture_2d<f32>;
 
 @group(3) @binding(0) var velocity_texture: texture_2d<f32>;
 
@@ -138,7 +156,12 @@ fn main(
   basepoints[index] = basepoint;
 
   let line = lines[index];
-  let velocity = textureSampleLevel(velocity_texture, linear_sampler, basepoint, 0.0).xy;
+  // The fluid steps less often than lines are placed on fast displays. Ease
+  // from the previous step to the latest one, a step behind, so the lines
+  // move smoothly instead of jumping with each step.
+  let previous_velocity = textureSampleLevel(previous_velocity_texture, linear_sampler, basepoint, 0.0).xy;
+  let latest_velocity = textureSampleLevel(velocity_texture, linear_sampler, basepoint, 0.0).xy;
+  let velocity = mix(previous_velocity, latest_velocity, uniforms.velocity_blend);
 
   // Blend the two noises when reaching the limit of the offset
   let scaled_pos = uniforms.line_noise_scale * basepoint;
@@ -161,8 +184,10 @@ fn main(
   // Basically, smoothstep(0.0, 0.4, length(velocity));
   // Maybe width and opacity should be on different easings.
   let width_boost = saturate(2.5 * length(velocity));
-  let new_line_width = smoothstep(0.0, 1.0, width_boost);
-  let opacity = smoothstep(0.0, 1.0, width_boost);
+  // Hide lines rooted inside an obstacle.
+  let open = 1.0 - step(0.5, textureSampleLevel(obstacle_texture, linear_sampler, basepoint, 0.0).x);
+  let new_line_width = open * smoothstep(0.0, 1.0, width_boost);
+  let opacity = open * smoothstep(0.0, 1.0, width_boost);
 
   var color: vec3<f32>;
   var color_momentum_boost = 3.0;
@@ -187,6 +212,13 @@ fn main(
       color_momentum_boost = 5.0;
       color_delta_boost = 10.0;
     }
+
+    // Dye
+    case 3u: {
+      color = textureSampleLevel(dye_texture, linear_sampler, basepoint, 0.0).rgb;
+      color_momentum_boost = 5.0;
+      color_delta_boost = 10.0;
+    }
   }
 
   let new_color_velocity
@@ -210,17 +242,101 @@ fn main(
 const pi = 3.141592653589793;
 const tau = 2.0 * pi;
 
-// Get a color from the ring buffer of colors.
+// Get a color from the gradient, which wraps around.
 // Limit specifies the value at which the color should wrap around.
 fn get_color(value: f32, limit: f32) -> vec4<f32> {
-  let size = f32(arrayLength(&color_buffer));
-  let slice = limit / size;
-  let raw_index = (value % limit) / slice;
-  let index = floor(raw_index);
-  let next_index = (index + 1.0) % size;
-  let interpolate = fract(raw_index);
-
-  let current_color = color_buffer[u32(index)];
-  let next_color = color_buffer[u32(next_index)];
-  return mix(current_color, next_color, interpolate);
+  let count = arrayLength(&color_buffer);
+  let position = fract(value / limit);
+
+  // The last stop at or before the position. Before the first stop, that's
+  // the last stop, from the previous time around.
+  var index = count - 1u;
+  for (var i = 0u; i < count; i++) {
+    if (color_buffer[i].position <= position) {
+      index = i;
+    }
+  }
+  let stop = color_buffer[index];
+  let next_stop = color_buffer[(index + 1u) % count];
+
+  // Both distances wrap around past 1.
+  let span = fract(next_stop.position - stop.position);
+  var interpolate = 0.0;
+  if (span > 0.0) {
+    interpolate = saturate(fract(position - stop.position) / span);
+  }
+
+  switch stop.interpolation {
+    case INTERPOLATION_SMOOTH: {
+      interpolate = smoothstep(0.0, 1.0, interpolate);
+    }
+    case INTERPOLATION_CONSTANT: {
+      interpolate = 0.0;
+    }
+    default: {}
+  }
+
+  switch uniforms.color_space {
+    case COLOR_SPACE_OKLAB: {
+      let color = mix(stop.color, next_stop.color, interpolate);
+      return vec4<f32>(oklab_to_srgb(color.xyz), color.a);
+    }
+    case COLOR_SPACE_OKLCH: {
+      let color = mix_oklch(stop.color, next_stop.color, interpolate);
+      return vec4<f32>(oklab_to_srgb(oklch_to_oklab(color.xyz)), color.a);
+    }
+    default: {
+      return mix(stop.color, next_stop.color, interpolate);
+    }
+  }
+}
+
+// The gradient stops arrive already converted into the gradient's color space
+// by `color::to_space`. These convert the blended color back into sRGB, and
+// match `color.rs`.
+
+// Below this chroma, an OKLCH color is grey and its hue means nothing.
+const ACHROMATIC_CHROMA: f32 = 1e-4;
+
+// Blend lightness, chroma and hue, taking the short way around the hue circle.
+// A grey takes on the hue of the color it's blended with.
+fn mix_oklch(start: vec4<f32>, end: vec4<f32>, amount: f32) -> vec4<f32> {
+  var start_hue = start.z;
+  var end_hue = end.z;
+  if (start.y < ACHROMATIC_CHROMA) {
+    start_hue = end_hue;
+  } else if (end.y < ACHROMATIC_CHROMA) {
+    end_hue = start_hue;
+  }
+
+  let turn = end_hue - start_hue + pi;
+  let delta = turn - tau * floor(turn / tau) - pi;
+  let color = mix(start, end, amount);
+  return vec4<f32>(color.xy, start_hue + delta * amount, color.a);
+}
+
+fn oklch_to_oklab(lch: vec3<f32>) -> vec3<f32> {
+  return vec3<f32>(lch.x, lch.y * cos(lch.z), lch.y * sin(lch.z));
+}
+
+fn oklab_to_srgb(lab: vec3<f32>) -> vec3<f32> {
+  let lms = vec3<f32>(
+    lab.x + 0.3963377774 * lab.y + 0.2158037573 * lab.z,
+    lab.x - 0.1055613458 * lab.y - 0.0638541728 * lab.z,
+    lab.x - 0.0894841775 * lab.y - 1.2914855480 * lab.z,
+  );
+  let lms_cubed = lms * lms * lms;
+
+  let rgb = vec3<f32>(
+    4.0767416621 * lms_cubed.x - 3.3077115913 * lms_cubed.y + 0.2309699292 * lms_cubed.z,
+    -1.2684380046 * lms_cubed.x + 2.6097574011 * lms_cubed.y - 0.3413193965 * lms_cubed.z,
+    -0.0041960863 * lms_cubed.x - 0.7034186147 * lms_cubed.y + 1.7076147010 * lms_cubed.z,
+  );
+  return linear_to_srgb(max(rgb, vec3<f32>(0.0)));
+}
+
+fn linear_to_srgb(rgb: vec3<f32>) -> vec3<f32> {
+  let low = rgb * 12.92;
+  let high = 1.055 * pow(rgb, vec3<f32>(1.0 / 2.4)) - 0.055;
+  return select(high, low, rgb <= vec3<f32>(0.0031308));
 }
@@ -0,0 +1,36 @@
+@group(0) @binding(0) var correction_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var pressure_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_pressure_texture: texture_storage_2d<r32float, write>;
+
+fn correction_at(cell: vec2<i32>) -> f32 {
+  let size = vec2<i32>(textureDimensions(correction_texture));
+  return textureLoad(correction_texture, clamp(cell, vec2<i32>(0), size - 1), 0).x;
+}
+
+// Adds the correction solved on the coarser grid, interpolated bilinearly
+// between the centres of the coarse cells.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(pressure_texture);
+  if (any(global_id.xy >= size)) {
+    return;
+  }
+
+  let position = 0.5 * (vec2<f32>(global_id.xy) + 0.5) - 0.5;
+  let cell = vec2<i32>(floor(position));
+  let t = fract(position);
+
+  let correction = mix(
+    mix(correction_at(cell), correction_at(cell + vec2<i32>(1, 0)), t.x),
+    mix(correction_at(cell + vec2<i32>(0, 1)), correction_at(cell + vec2<i32>(1, 1)), t.x),
+    t.y,
+  );
+
+  let pressure = textureLoad(pressure_texture, global_id.xy, 0).x;
+
+  textureStore(out_pressure_texture, global_id.xy, vec4<f32>(pressure + correction, 0.0, 0.0, 0.0));
+}
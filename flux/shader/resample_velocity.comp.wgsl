@@ -0,0 +1,23 @@
+@group(0) @binding(1) var linear_sampler: sampler;
+
+@group(1) @binding(0) var velocity_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_texture: texture_storage_2d<rgba16float, write>;
+
+// Bilinearly resample the velocity field, or the dye, into a texture of a
+// different size. Velocities are stored independently of the fluid resolution,
+// so the values carry over unscaled.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(out_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let sample_position = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+  let value = textureSampleLevel(velocity_texture, linear_sampler, sample_position, 0.0);
+
+  textureStore(out_texture, global_id.xy, value);
+}
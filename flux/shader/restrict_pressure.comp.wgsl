@@ -0,0 +1,84 @@
+struct FluidUniforms {
+  timestep: f32,
+  dissipation: f32,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var divergence_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var pressure_texture: texture_2d<f32>;
+@group(2) @binding(1) var out_coarse_divergence_texture: texture_storage_2d<r32float, write>;
+
+@group(3) @binding(0) var out_coarse_pressure_texture: texture_storage_2d<r32float, write>;
+
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
+// Like `solve_pressure.comp.wgsl`, walls and obstacles reflect the pressure
+// of the cell next to them, and a periodic domain wraps around.
+fn neighbour_pressure(cell: vec2<i32>, offset: vec2<i32>, pressure: f32) -> f32 {
+  let size = vec2<i32>(textureDimensions(pressure_texture));
+  var neighbour = cell + offset;
+  if (uniforms.boundary == BOUNDARY_PERIODIC) {
+    neighbour = (neighbour % size + size) % size;
+  }
+  if (any(neighbour < vec2<i32>(0)) || any(neighbour >= size) || is_solid(neighbour)) {
+    return pressure;
+  }
+  return textureLoad(pressure_texture, neighbour, 0).x;
+}
+
+// How far the pressure at `cell` is from solving the pressure equation.
+fn residual(cell: vec2<i32>) -> f32 {
+  let pressure = textureLoad(pressure_texture, cell, 0).x;
+  let divergence = textureLoad(divergence_texture, cell, 0).x;
+
+  let l = neighbour_pressure(cell, vec2<i32>(-1, 0), pressure);
+  let r = neighbour_pressure(cell, vec2<i32>(1, 0), pressure);
+  let b = neighbour_pressure(cell, vec2<i32>(0, -1), pressure);
+  let t = neighbour_pressure(cell, vec2<i32>(0, 1), pressure);
+
+  return divergence - (l + r + b + t - 4.0 * pressure);
+}
+
+// Sums the residual over each 2x2 block of fine cells. The coarse cells are
+// twice as wide, which scales the stencil by 4, so the sum is the right
+// right-hand side for the coarse correction. The correction starts at zero.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let coarse_size = textureDimensions(out_coarse_pressure_texture);
+  if (any(global_id.xy >= coarse_size)) {
+    return;
+  }
+
+  let size = vec2<i32>(textureDimensions(pressure_texture));
+  let origin = 2 * vec2<i32>(global_id.xy);
+
+  var sum = 0.0;
+  for (var y = 0; y < 2; y++) {
+    for (var x = 0; x < 2; x++) {
+      let cell = origin + vec2<i32>(x, y);
+      // Nothing reads the pressure inside obstacles.
+      if (all(cell < size) && !is_solid(cell)) {
+        sum += residual(cell);
+      }
+    }
+  }
+
+  textureStore(out_coarse_divergence_texture, global_id.xy, vec4<f32>(sum, 0.0, 0.0, 0.0));
+  textureStore(out_coarse_pressure_texture, global_id.xy, vec4<f32>(0.0));
+}
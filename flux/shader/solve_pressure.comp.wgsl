@@ -6,23 +6,39 @@ struct FluidUniforms {
   r_beta: f32,
   center_factor: f32,
   stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
 }
 
 @group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
 @group(0) @binding(1) var linear_sampler: sampler;
 @group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
 
 @group(1) @binding(0) var divergence_texture: texture_2d<f32>;
 
 @group(2) @binding(0) var pressure_texture: texture_2d<f32>;
 @group(2) @binding(1) var out_pressure_texture: texture_storage_2d<r32float, write>;
 
+// Damps each sweep towards the previous pressure. Plain Jacobi (1.0) leaves
+// the checkerboard error untouched, so the multigrid smoother uses less.
+override jacobi_weight: f32 = 1.0;
+
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
 @compute
 @workgroup_size(16, 16, 1)
 fn main(
   @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
   let size = textureDimensions(pressure_texture);
+  // The coarser multigrid levels aren't a multiple of the workgroup size.
+  if (any(global_id.xy >= size)) {
+    return;
+  }
+
   let sample_position = vec2<f32>(global_id.xy) / vec2<f32>(size);
 
   let pressure = textureLoad(pressure_texture, global_id.xy, 0).x;
@@ -33,18 +49,38 @@ fn main(
   var b = textureSampleLevel(pressure_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(0, -1)).x;
   var t = textureSampleLevel(pressure_texture, nearest_sampler, sample_position, 0.0, vec2<i32>(0, 1)).x;
 
-  if (global_id.x == 0u) {
+  // Walls reflect the pressure next to them. A periodic domain samples
+  // across the edges instead; see `create_samplers`.
+  if (uniforms.boundary != BOUNDARY_PERIODIC) {
+    if (global_id.x == 0u) {
+      l = pressure;
+    } else if (global_id.x == size.x - 1u) {
+      r = pressure;
+    }
+    if (global_id.y == 0u) {
+      b = pressure;
+    } else if (global_id.y == size.y - 1u) {
+      t = pressure;
+    }
+  }
+
+  // Treat obstacles like the domain edges: no pressure gradient across them.
+  let cell = vec2<i32>(global_id.xy);
+  if (is_solid(cell + vec2<i32>(-1, 0))) {
     l = pressure;
-  } else if (global_id.x == size.x - 1u) {
+  }
+  if (is_solid(cell + vec2<i32>(1, 0))) {
     r = pressure;
   }
-  if (global_id.y == 0u) {
+  if (is_solid(cell + vec2<i32>(0, -1))) {
     b = pressure;
-  } else if (global_id.y == size.y - 1u) {
+  }
+  if (is_solid(cell + vec2<i32>(0, 1))) {
     t = pressure;
   }
 
-  let new_pressure = uniforms.r_beta * (l + r + b + t + uniforms.alpha * divergence);
+  let jacobi_pressure = uniforms.r_beta * (l + r + b + t + uniforms.alpha * divergence);
+  let new_pressure = mix(pressure, jacobi_pressure, jacobi_weight);
 
   textureStore(out_pressure_texture, global_id.xy, vec4<f32>(new_pressure, 0.0, 0.0, 0.0));
 }
@@ -0,0 +1,47 @@
+const MAX_IMPULSES: u32 = 32u;
+
+struct Impulse {
+  position: vec2<f32>,
+  velocity: vec2<f32>,
+  radius: f32,
+  padding0: f32,
+  padding1: f32,
+  padding2: f32,
+}
+
+struct ImpulseUniforms {
+  count: u32,
+  aspect: f32,
+  padding: vec2<f32>,
+  impulses: array<Impulse, MAX_IMPULSES>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: ImpulseUniforms;
+
+@group(1) @binding(0) var velocity_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_velocity_texture: texture_storage_2d<rgba16float, write>;
+
+// Add a Gaussian splat of velocity around each impulse. Positions and radii
+// are in texture space, with distances measured in units of the domain height.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(out_velocity_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let sample_position = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+
+  var velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
+  for (var i = 0u; i < min(uniforms.count, MAX_IMPULSES); i++) {
+    let impulse = uniforms.impulses[i];
+    let offset = (sample_position - impulse.position) * vec2<f32>(uniforms.aspect, 1.0);
+    let weight = exp(-dot(offset, offset) / (impulse.radius * impulse.radius));
+    velocity += weight * impulse.velocity;
+  }
+
+  textureStore(out_velocity_texture, global_id.xy, vec4<f32>(velocity, 0.0, 0.0));
+}
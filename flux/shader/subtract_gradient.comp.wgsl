@@ -6,17 +6,24 @@ struct FluidUniforms {
   r_beta: f32,
   center_factor: f32,
   stencil_factor: f32,
+  dye_dissipation: f32,
+  vorticity: f32,
+  boundary: u32,
 }
 
 @group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
 @group(0) @binding(1) var linear_sampler: sampler;
 @group(0) @binding(2) var nearest_sampler: sampler;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
 
 @group(1) @binding(0) var pressure_texture: texture_2d<f32>;
 
 @group(2) @binding(0) var velocity_texture: texture_2d<f32>;
 @group(2) @binding(1) var out_velocity_texture: texture_storage_2d<rgba16float, write>;
 
+#include "fluid.inc.wgsl"
+#include "boundary.inc.wgsl"
+
 @compute
 @workgroup_size(16, 16, 1)
 fn main(
@@ -57,16 +64,31 @@ fn main(
   //  A number of things actually work here: -1.0 adjustment for velocity,
   //  setting just the relevant component of velocity to zero, and flipping
   //  pressures along relevant axis. All seem stable, but experiment!
+  //
+  //  Which walls there are, and what they stop, depends on the boundary. See
+  //  `wall_mask`.
+
+  let cell = vec2<i32>(global_id.xy);
+  var boundary_condition = wall_mask(cell, vec2<i32>(size));
 
-  var boundary_condition = vec2<f32>(1.0);
-  if (global_id.x == 0u) {
+  // Obstacles get the same treatment as the edges of the domain.
+  if (is_solid(cell)) {
+    boundary_condition = vec2<f32>(0.0);
+  }
+  if (is_solid(cell + vec2<i32>(-1, 0))) {
+    l = pressure;
     boundary_condition.x = 0.0;
-  } else if (global_id.x == size.x - 1u) {
+  }
+  if (is_solid(cell + vec2<i32>(1, 0))) {
+    r = pressure;
     boundary_condition.x = 0.0;
   }
-  if (global_id.y == 0u) {
+  if (is_solid(cell + vec2<i32>(0, -1))) {
+    b = pressure;
     boundary_condition.y = 0.0;
-  } else if (global_id.y == size.y - 1u) {
+  }
+  if (is_solid(cell + vec2<i32>(0, 1))) {
+    t = pressure;
     boundary_condition.y = 0.0;
   }
 
@@ -0,0 +1,192 @@
+// Conversions between sRGB and the perceptual OKLab and OKLCH spaces.
+//
+// Gradients blend their stops in one of these spaces; see
+// `Settings::gradient_color_space`. The stops are converted here before
+// they're uploaded, and `place_lines.comp.wgsl` converts the blended color
+// back to sRGB. Keep the two in sync.
+//
+// Colors are RGBA arrays. Alpha is straight and is never converted.
+
+use crate::settings::ColorSpace;
+
+// Below this chroma, an OKLCH color is grey and its hue means nothing.
+const ACHROMATIC_CHROMA: f32 = 1e-4;
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts an sRGB color into OKLab: lightness, then the green–red and
+/// blue–yellow axes.
+// The published OKLab matrices, digit for digit, as in the shader.
+#[allow(clippy::excessive_precision)]
+pub fn srgb_to_oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = [r, g, b].map(srgb_to_linear);
+
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// Converts an OKLab color back into sRGB. Colors outside the sRGB gamut
+/// are clipped at 0, but can come out above 1.
+#[allow(clippy::excessive_precision)]
+pub fn oklab_to_srgb([lightness, a, b]: [f32; 3]) -> [f32; 3] {
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+    .map(|value| linear_to_srgb(value.max(0.0)))
+}
+
+/// Converts an OKLab color into OKLCH: lightness, chroma and hue, in
+/// radians.
+pub fn oklab_to_oklch([lightness, a, b]: [f32; 3]) -> [f32; 3] {
+    [lightness, a.hypot(b), b.atan2(a)]
+}
+
+pub fn oklch_to_oklab([lightness, chroma, hue]: [f32; 3]) -> [f32; 3] {
+    [lightness, chroma * hue.cos(), chroma * hue.sin()]
+}
+
+/// Converts an sRGB color into `space`, as the gradient stops are uploaded.
+pub fn to_space(color: [f32; 4], space: ColorSpace) -> [f32; 4] {
+    let [r, g, b, alpha] = color;
+    let [x, y, z] = match space {
+        ColorSpace::Srgb => return color,
+        ColorSpace::Oklab => srgb_to_oklab([r, g, b]),
+        ColorSpace::Oklch => oklab_to_oklch(srgb_to_oklab([r, g, b])),
+    };
+    [x, y, z, alpha]
+}
+
+/// Converts a color in `space` back into sRGB.
+pub fn from_space(color: [f32; 4], space: ColorSpace) -> [f32; 4] {
+    let [x, y, z, alpha] = color;
+    let [r, g, b] = match space {
+        ColorSpace::Srgb => return color,
+        ColorSpace::Oklab => oklab_to_srgb([x, y, z]),
+        ColorSpace::Oklch => oklab_to_srgb(oklch_to_oklab([x, y, z])),
+    };
+    [r, g, b, alpha]
+}
+
+/// Blends two colors, already in `space`, by `amount` from 0 to 1.
+///
+/// OKLCH hues take the short way around the hue circle. A grey takes on the
+/// hue of the color it's blended with, so it doesn't swing through an
+/// unrelated one.
+pub fn mix(from: [f32; 4], to: [f32; 4], amount: f32, space: ColorSpace) -> [f32; 4] {
+    if space == ColorSpace::Oklch {
+        let (from_hue, to_hue) = match (from[1] < ACHROMATIC_CHROMA, to[1] < ACHROMATIC_CHROMA) {
+            (true, false) => (to[2], to[2]),
+            (false, true) => (from[2], from[2]),
+            _ => (from[2], to[2]),
+        };
+        let turn = std::f32::consts::TAU;
+        let delta = (to_hue - from_hue + turn / 2.0).rem_euclid(turn) - turn / 2.0;
+        let mut mixed = lerp(from, to, amount);
+        mixed[2] = from_hue + delta * amount;
+        return mixed;
+    }
+
+    lerp(from, to, amount)
+}
+
+fn lerp(from: [f32; 4], to: [f32; 4], amount: f32) -> [f32; 4] {
+    std::array::from_fn(|i| from[i] + (to[i] - from[i]) * amount)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+    const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+
+    fn chroma(color: [f32; 4]) -> f32 {
+        oklab_to_oklch(srgb_to_oklab([color[0], color[1], color[2]]))[1]
+    }
+
+    #[test]
+    fn converts_to_oklab() {
+        let [l, a, b] = srgb_to_oklab([1.0, 1.0, 1.0]);
+        assert_relative_eq!(l, 1.0, epsilon = 1e-4);
+        assert_relative_eq!(a, 0.0, epsilon = 1e-4);
+        assert_relative_eq!(b, 0.0, epsilon = 1e-4);
+
+        let [l, a, b] = srgb_to_oklab([1.0, 0.0, 0.0]);
+        assert_relative_eq!(l, 0.62796, epsilon = 1e-4);
+        assert_relative_eq!(a, 0.22486, epsilon = 1e-4);
+        assert_relative_eq!(b, 0.12585, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn round_trips_through_each_space() {
+        let color = [0.9, 0.35, 0.05, 0.5];
+        for space in [ColorSpace::Srgb, ColorSpace::Oklab, ColorSpace::Oklch] {
+            let round_trip = from_space(to_space(color, space), space);
+            for (value, expected) in round_trip.into_iter().zip(color) {
+                assert_relative_eq!(value, expected, epsilon = 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_midpoints_vivid() {
+        let midpoint = |space| {
+            from_space(
+                mix(to_space(BLUE, space), to_space(YELLOW, space), 0.5, space),
+                space,
+            )
+        };
+
+        // Blue and yellow meet at grey in sRGB.
+        assert!(chroma(midpoint(ColorSpace::Srgb)) < 1e-3);
+        assert!(chroma(midpoint(ColorSpace::Oklch)) > 0.1);
+        // OKLab still cuts through the middle, but stays brighter.
+        let srgb_lightness = srgb_to_oklab([0.5, 0.5, 0.5])[0];
+        let oklab = midpoint(ColorSpace::Oklab);
+        assert!(srgb_to_oklab([oklab[0], oklab[1], oklab[2]])[0] > srgb_lightness);
+    }
+
+    #[test]
+    fn mixes_hues_the_short_way_around() {
+        let space = ColorSpace::Oklch;
+        let from = [0.7, 0.1, 350f32.to_radians(), 1.0];
+        let to = [0.7, 0.1, 10f32.to_radians(), 1.0];
+        let [_, _, hue, _] = mix(from, to, 0.5, space);
+        assert_relative_eq!(hue.cos(), 1.0, epsilon = 1e-4);
+
+        // A grey takes the hue of the other color.
+        let grey = to_space([0.5, 0.5, 0.5, 1.0], space);
+        let red = to_space(RED, space);
+        assert_relative_eq!(mix(grey, red, 0.25, space)[2], red[2]);
+        assert_relative_eq!(mix(red, grey, 0.75, space)[2], red[2]);
+    }
+}
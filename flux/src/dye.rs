@@ -0,0 +1,90 @@
+use crate::color::srgb_to_linear;
+use crate::image_fit::ImageFit;
+
+use half::f16;
+use image::RgbaImage;
+
+/// Converts an sRGB color with straight alpha into the linear, premultiplied
+/// RGBA the dye is stored as.
+pub fn to_linear_premultiplied(color: [f32; 4]) -> [f32; 4] {
+    let alpha = color[3];
+    [
+        alpha * srgb_to_linear(color[0]),
+        alpha * srgb_to_linear(color[1]),
+        alpha * srgb_to_linear(color[2]),
+        alpha,
+    ]
+}
+
+/// Samples `image` into one RGBA texel per fluid cell, in the row order
+/// `fluid::Context::set_dye` expects.
+///
+/// The image is fitted inside the view; see `ImageFit`. Cells outside the
+/// image are left clear.
+pub fn rasterize(
+    image: &RgbaImage,
+    fluid_size: wgpu::Extent3d,
+    view_size: wgpu::Extent3d,
+    view_scale: f32,
+) -> Vec<[f16; 4]> {
+    let fit = ImageFit::new(image.dimensions(), view_size, view_scale);
+    let mut texels = vec![[f16::ZERO; 4]; (fluid_size.width * fluid_size.height) as usize];
+
+    for y in 0..fluid_size.height {
+        for x in 0..fluid_size.width {
+            let Some((image_x, image_y)) = fit.pixel(fluid_size, x, y) else {
+                continue;
+            };
+
+            let pixel = image
+                .get_pixel(image_x, image_y)
+                .0
+                .map(|c| c as f32 / 255.0);
+            texels[(y * fluid_size.width + x) as usize] =
+                to_linear_premultiplied(pixel).map(f16::from_f32);
+        }
+    }
+
+    texels
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn converts_to_linear_premultiplied() {
+        assert_eq!(
+            to_linear_premultiplied([1.0, 0.0, 1.0, 1.0]),
+            [1.0, 0.0, 1.0, 1.0]
+        );
+
+        let [r, g, b, a] = to_linear_premultiplied([0.5, 0.5, 0.5, 0.5]);
+        approx::assert_relative_eq!(r, 0.5 * 0.21404, epsilon = 1e-4);
+        assert_eq!((r, g), (g, b));
+        assert_eq!(a, 0.5);
+    }
+
+    #[test]
+    fn rasterizes_into_texture_space() {
+        let image = RgbaImage::from_fn(1, 2, |_, y| {
+            if y == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+        let size = |width, height| wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texels = rasterize(&image, size(1, 2), size(100, 200), 1.0);
+
+        // The top of the image lands in the last row.
+        let one = f16::ONE;
+        let zero = f16::ZERO;
+        assert_eq!(texels, [[zero, zero, one, one], [one, zero, zero, one]]);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::settings::FieldError;
+
+use std::path::PathBuf;
+
+/// Everything that can go wrong while creating or driving a Flux instance.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "The GPU doesn't support Flux: {limit} is {supported}, but Flux needs at least {required}"
+    )]
+    UnsupportedDevice {
+        limit: &'static str,
+        required: u32,
+        supported: u32,
+    },
+
+    #[error("Failed to parse settings: {0}")]
+    ParseSettings(String),
+
+    #[error(
+        "Settings version {0} is newer than this version of Flux supports ({supported})",
+        supported = crate::settings::VERSION
+    )]
+    UnsupportedSettingsVersion(u32),
+
+    #[error("Invalid settings: {}", join(.0))]
+    InvalidSettings(Vec<FieldError>),
+
+    #[error("Failed to read image from {}: {source}", .path.display())]
+    ReadImage {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to decode image: {0}")]
+    DecodeImage(#[from] image::ImageError),
+
+    #[error("Failed to create shaders and pipelines: {0}")]
+    CreatePipeline(#[source] wgpu::Error),
+
+    #[error("Failed to read back from the GPU: {0}")]
+    Readback(String),
+
+    #[error("Flux was not created with an offscreen target")]
+    NoOffscreenTarget,
+
+    #[error("Invalid snapshot: {0}")]
+    Snapshot(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn join(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|err| err.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,4 +1,5 @@
-use crate::{grid, render, rng, settings};
+use crate::error::{Error, Result};
+use crate::{dye, grid, obstacle, render, rng, settings, snapshot};
 use settings::Settings;
 
 use std::sync::Arc;
@@ -7,6 +8,13 @@ use std::sync::Mutex;
 // The time at which the animation timer will reset to zero.
 const MAX_ELAPSED_TIME: f32 = 1000.0;
 const MAX_FRAME_TIME: f32 = 1.0 / 10.0;
+// The most fluid steps `compute` runs in one frame. When a slow frame falls
+// further behind, the fluid skips ahead rather than spending even longer
+// catching up.
+const MAX_FLUID_STEPS_PER_FRAME: u32 = 4;
+// How many colors `sample_palette_from_image` picks, as many as the color
+// wheel presets have.
+const PALETTE_SIZE: usize = 6;
 
 /// Optional GPU capabilities discovered at device-creation time.
 ///
@@ -22,6 +30,18 @@ pub struct BackendCaps {
     pub float32_filterable: bool,
 }
 
+/// Numbers that describe what the simulation is doing, for debug overlays and
+/// logging.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DebugStats {
+    /// How many substeps each fluid step was split into last frame. Always 1
+    /// without `Settings::adaptive_timestep`.
+    pub substeps: u32,
+    /// The fastest speed in the fluid, in cells per unit of timestep, as last
+    /// read back from the GPU. Lags the fluid by a couple of frames.
+    pub max_speed: Option<f32>,
+}
+
 pub struct Flux {
     settings: Arc<Settings>,
     logical_size: wgpu::Extent3d,
@@ -31,9 +51,20 @@ pub struct Flux {
     fluid: render::fluid::Context,
     pub lines: render::lines::Context,
     noise_generator: render::noise::NoiseGenerator,
+    impulses: render::impulse::Context,
     debug_texture: render::texture::Context,
 
     pub color_image: Arc<Mutex<Option<image::RgbaImage>>>,
+    // The `ColorMode::ImageFile` or `ColorMode::ImagePalette` image last
+    // loaded, if any.
+    color_image_source: Option<ColorImageSource>,
+    // The colors last picked from an image, kept so that updates in
+    // `ColorMode::ImagePalette` don't lose them.
+    color_palette: Option<Vec<settings::GradientStop>>,
+    obstacle_image: Option<image::RgbaImage>,
+
+    // Set when Flux renders into its own texture instead of a host surface.
+    offscreen: Option<render::offscreen::Target>,
 
     // A timestamp in milliseconds. Either host or video time.
     last_timestamp: f64,
@@ -42,17 +73,42 @@ pub struct Flux {
     elapsed_time: f32,
 
     fluid_frame_time: f32,
+
+    // The source of everything random in this instance. See `rng`.
+    rng: rng::Rng,
+
+    // How many substeps the fluid took per step on the last frame.
+    substeps: u32,
 }
 
 impl Flux {
-    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, settings: &Arc<Settings>) {
+    /// Applies new settings. Invalid settings are rejected before anything
+    /// changes; see `Settings::validate`.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &Arc<Settings>,
+    ) -> Result<()> {
+        settings.validate().map_err(Error::InvalidSettings)?;
+
+        // Read a new image before changing anything, so a missing or broken
+        // file leaves the previous settings in place.
+        let color_image_source = color_image_source(&settings.color_mode);
+        let color_image = match &color_image_source {
+            Some(source) if self.color_image_source.as_ref() != Some(source) => {
+                read_color_image(source)?
+            }
+            _ => None,
+        };
+
         self.settings = Arc::clone(settings);
 
         // `grid_spacing` determines the grid dimensions, so a change to it
         // re-grids at the current window size. Rebuild and compare; if the
         // dimensions moved, carry the line state into the new grid with the same
         // resample used for window resizes (rather than springing back from
-        // zero), and refresh the grid-dependent noise state.
+        // zero).
         let grid = grid::Grid::new(
             self.logical_size.width,
             self.logical_size.height,
@@ -61,16 +117,33 @@ impl Flux {
         let regridded = grid.columns != self.grid.columns || grid.rows != self.grid.rows;
         if regridded {
             self.grid = grid;
-            self.noise_generator.resize(
-                device,
-                2 * self.settings.fluid_size,
-                self.grid.scaling_ratio,
-            );
         }
 
-        self.fluid
+        // The fluid and noise textures follow both the grid and `fluid_size`.
+        // Each is only reallocated if its size actually changed, or, for the
+        // fluid, if the boundary starts or stops wrapping around.
+        let reallocated = self
+            .fluid
             .update(device, queue, self.grid.scaling_ratio, &self.settings);
-        self.noise_generator.update(&self.settings);
+        self.noise_generator.resize(
+            device,
+            2 * self.settings.fluid_size,
+            self.grid.scaling_ratio,
+        );
+        self.noise_generator
+            .update(device, &self.settings, &mut self.rng);
+
+        if regridded || reallocated {
+            self.refresh_debug_textures(device);
+            self.lines
+                .set_obstacle_texture_view(device, self.fluid.get_obstacle_texture_view());
+            self.lines.set_previous_velocity_texture_view(
+                device,
+                self.fluid.get_previous_velocity_texture_view(),
+            );
+            self.lines
+                .set_dye_texture_view(device, self.fluid.get_dye_texture_view());
+        }
 
         if regridded {
             self.lines
@@ -79,6 +152,133 @@ impl Flux {
             self.lines
                 .update(device, queue, self.logical_size, &self.grid, &self.settings);
         }
+
+        // The mask settings or the view scale may have changed.
+        if self.obstacle_image.is_some() {
+            self.refresh_obstacles(queue);
+        }
+        self.upload_dye_emitters(queue);
+
+        self.color_image_source = color_image_source;
+        match color_image {
+            Some(image) => self.apply_color_image(device, queue, &image),
+            // The lines were just given the color space of the new settings,
+            // so the palette needs converting again.
+            None if matches!(
+                self.settings.color_mode,
+                settings::ColorMode::ImagePalette { .. }
+            ) =>
+            {
+                self.set_color_palette(device, queue);
+            }
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    /// Turns `image` into solid obstacles that the fluid flows around, using
+    /// `Settings::obstacle_mask`. Lines rooted inside an obstacle are hidden.
+    ///
+    /// The image is fitted inside the view, so it keeps its place on screen
+    /// as the window resizes.
+    pub fn set_obstacle_mask(&mut self, queue: &wgpu::Queue, image: &image::RgbaImage) {
+        self.obstacle_image = Some(image.clone());
+        self.refresh_obstacles(queue);
+    }
+
+    pub fn clear_obstacle_mask(&mut self, queue: &wgpu::Queue) {
+        self.obstacle_image = None;
+        self.refresh_obstacles(queue);
+    }
+
+    fn refresh_obstacles(&self, queue: &wgpu::Queue) {
+        let fluid_size = self.fluid.get_fluid_size();
+        let cells = match &self.obstacle_image {
+            Some(image) => obstacle::rasterize(
+                image,
+                &self.settings.obstacle_mask,
+                fluid_size,
+                self.logical_size,
+                self.settings.view_scale,
+            ),
+            None => vec![0; (fluid_size.width * fluid_size.height) as usize],
+        };
+        self.fluid.set_obstacles(queue, &cells);
+    }
+
+    /// Replaces the dye with `image`, fitted inside the view like obstacle
+    /// masks are. The dye is only advected when something uses it; see
+    /// `Settings::uses_dye`.
+    pub fn seed_dye(&mut self, queue: &wgpu::Queue, image: &image::RgbaImage) {
+        let texels = dye::rasterize(
+            image,
+            self.fluid.get_fluid_size(),
+            self.logical_size,
+            self.settings.view_scale,
+        );
+        self.fluid.set_dye(queue, &texels);
+    }
+
+    // Emitters are placed in the view, so they move with the view scale and
+    // the window's aspect ratio.
+    fn upload_dye_emitters(&mut self, queue: &wgpu::Queue) {
+        let zoom = self.settings.view_scale;
+        let emitters: Vec<_> = self
+            .settings
+            .dye_emitters
+            .iter()
+            .map(|emitter| {
+                render::fluid::DyeEmitter::new(
+                    [
+                        0.5 + (emitter.position[0] - 0.5) / zoom,
+                        0.5 - (emitter.position[1] - 0.5) / zoom,
+                    ],
+                    emitter.radius / zoom,
+                    dye::to_linear_premultiplied(emitter.color),
+                    emitter.rate,
+                )
+            })
+            .collect();
+        let aspect = self.logical_size.width as f32 / self.logical_size.height as f32;
+        self.fluid.set_dye_emitters(queue, aspect, &emitters);
+    }
+
+    /// Pushes the fluid around `position`, e.g. from a pointer drag.
+    ///
+    /// `position` and `radius` are in logical pixels, measured from the
+    /// top-left corner of the view, and `velocity` is in logical pixels per
+    /// second. The impulse is applied in the next fluid step.
+    pub fn add_impulse(&mut self, position: [f32; 2], velocity: [f32; 2], radius: f32) {
+        if !position
+            .iter()
+            .chain(velocity.iter())
+            .chain(std::iter::once(&radius))
+            .all(|v| v.is_finite())
+            || radius <= 0.0
+        {
+            return;
+        }
+
+        // Map from the window into texture space, undoing the view scale the
+        // lines are drawn with. Texture space has y pointing up.
+        let width = self.logical_size.width as f32;
+        let height = self.logical_size.height as f32;
+        let zoom = self.settings.view_scale;
+        let position = [
+            0.5 + (position[0] / width - 0.5) / zoom,
+            0.5 - (position[1] / height - 0.5) / zoom,
+        ];
+
+        // Velocities and the radius are measured in view heights.
+        let scale = 1.0 / (height * zoom);
+        let velocity = [velocity[0] * scale, -velocity[1] * scale];
+
+        self.impulses.push(render::impulse::Impulse::new(
+            position,
+            velocity,
+            radius * scale,
+        ));
     }
 
     pub fn sample_colors_from_image(
@@ -89,6 +289,52 @@ impl Flux {
     ) {
         let texture_view = render::color::load_color_texture(device, queue, image);
         self.sample_colors_from_texture_view(device, queue, texture_view);
+        self.seed_dye(queue, image);
+    }
+
+    /// Colors the lines from the dominant colors of `image` around a color
+    /// wheel, rather than from where the colors are in the image. See
+    /// `render::color::extract_palette`.
+    ///
+    /// This is how `ColorMode::ImagePalette` is applied, and the palette
+    /// lasts as long as that mode does. In any other mode, the next
+    /// `update` recolors the lines from the settings.
+    pub fn sample_palette_from_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::RgbaImage,
+    ) {
+        let palette = render::color::extract_palette(image, PALETTE_SIZE);
+        if palette.is_empty() {
+            log::warn!("The image has no opaque pixels to pick a palette from");
+            return;
+        }
+
+        self.color_palette = Some(settings::GradientStop::evenly_spaced(&palette));
+        self.set_color_palette(device, queue);
+    }
+
+    fn set_color_palette(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if let Some(palette) = &self.color_palette {
+            self.lines
+                .set_gradient(device, queue, palette, self.settings.gradient_color_space);
+        }
+    }
+
+    // Colors the lines from a newly loaded image, as the color mode asks.
+    fn apply_color_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::RgbaImage,
+    ) {
+        match self.settings.color_mode {
+            settings::ColorMode::ImagePalette { .. } => {
+                self.sample_palette_from_image(device, queue, image)
+            }
+            _ => self.sample_colors_from_image(device, queue, image),
+        }
     }
 
     pub fn sample_colors_from_texture_view(
@@ -112,10 +358,19 @@ impl Flux {
         physical_height: u32,
         caps: BackendCaps,
         settings: &Arc<Settings>,
-    ) -> Result<Flux, String> {
+    ) -> Result<Flux> {
         log::info!("✨ Initialising Flux");
 
-        rng::init_from_seed(&settings.seed);
+        settings.validate().map_err(Error::InvalidSettings)?;
+        check_device_limits(&device.limits())?;
+
+        let color_image_source = color_image_source(&settings.color_mode);
+        let color_image = match &color_image_source {
+            Some(source) => read_color_image(source)?,
+            None => None,
+        };
+
+        let mut rng = rng::Rng::from_seed(&settings.seed);
 
         let logical_size = wgpu::Extent3d {
             width: logical_width,
@@ -133,6 +388,10 @@ impl Flux {
 
         let grid = grid::Grid::new(logical_width, logical_height, settings.grid_spacing);
 
+        // Catch shader compilation and pipeline validation errors, instead of
+        // leaving them to the device's uncaptured error handler.
+        let error_scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+
         let fluid = render::fluid::Context::new(device, queue, grid.scaling_ratio, caps, settings);
 
         let lines = render::lines::Context::new(
@@ -142,6 +401,9 @@ impl Flux {
             logical_size,
             &grid,
             settings,
+            fluid.get_obstacle_texture_view(),
+            fluid.get_previous_velocity_texture_view(),
+            fluid.get_dye_texture_view(),
         );
 
         let mut noise_generator_builder = render::noise::NoiseGeneratorBuilder::new(
@@ -152,7 +414,9 @@ impl Flux {
         settings.noise_channels.iter().for_each(|channel| {
             noise_generator_builder.add_channel(channel);
         });
-        let noise_generator = noise_generator_builder.build(device, queue, caps);
+        let noise_generator = noise_generator_builder.build(device, queue, caps, &mut rng);
+
+        let impulses = render::impulse::Context::new(device);
 
         let debug_texture = render::texture::Context::new(
             device,
@@ -162,10 +426,15 @@ impl Flux {
                 ("noise", noise_generator.get_noise_texture_view()),
                 ("pressure", fluid.get_pressure_texture_view()),
                 ("divergence", fluid.get_divergence_texture_view()),
+                ("vorticity", fluid.get_vorticity_texture_view()),
             ],
         );
 
-        Ok(Flux {
+        if let Some(err) = pop_error_scope_now(error_scope) {
+            return Err(Error::CreatePipeline(err));
+        }
+
+        let mut flux = Flux {
             settings: Arc::clone(settings),
             logical_size,
             physical_size,
@@ -174,14 +443,60 @@ impl Flux {
             grid,
             lines,
             noise_generator,
+            impulses,
             debug_texture,
             color_image: Arc::new(Mutex::new(None)),
+            color_image_source: None,
+            color_palette: None,
+            obstacle_image: None,
+            offscreen: None,
 
             last_timestamp: 0.0,
             elapsed_time: 0.0,
 
             fluid_frame_time: 0.0,
-        })
+
+            rng,
+
+            substeps: 1,
+        };
+        flux.upload_dye_emitters(queue);
+
+        flux.color_image_source = color_image_source;
+        if let Some(image) = color_image {
+            flux.apply_color_image(device, queue, &image);
+        }
+
+        Ok(flux)
+    }
+
+    /// Creates a Flux instance that renders into a texture it owns, rather than
+    /// into a window surface. Use `render_to_image` or `animate_to_image` to
+    /// read frames back.
+    ///
+    /// The logical and physical sizes are both `width`x`height`.
+    pub fn new_offscreen(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        caps: BackendCaps,
+        settings: &Arc<Settings>,
+    ) -> Result<Flux> {
+        let mut flux = Self::new(
+            device,
+            queue,
+            render::offscreen::Target::FORMAT,
+            width,
+            height,
+            width,
+            height,
+            caps,
+            settings,
+        )?;
+        flux.offscreen = Some(render::offscreen::Target::new(device, width, height));
+
+        Ok(flux)
     }
 
     pub fn resize(
@@ -213,12 +528,52 @@ impl Flux {
         self.logical_size = logical_size;
         self.physical_size = physical_size;
 
-        // self.fluid.resize(device, self.grid.scaling_ratio);
+        if self.offscreen.is_some() {
+            self.offscreen = Some(render::offscreen::Target::new(
+                device,
+                physical_width,
+                physical_height,
+            ));
+        }
+
+        // The fluid domain follows the window's aspect ratio.
+        self.fluid
+            .update(device, queue, self.grid.scaling_ratio, &self.settings);
         self.noise_generator.resize(
             device,
             2 * self.settings.fluid_size,
             self.grid.scaling_ratio,
         );
+        self.refresh_debug_textures(device);
+        self.lines
+            .set_obstacle_texture_view(device, self.fluid.get_obstacle_texture_view());
+        self.lines.set_previous_velocity_texture_view(
+            device,
+            self.fluid.get_previous_velocity_texture_view(),
+        );
+        self.lines
+            .set_dye_texture_view(device, self.fluid.get_dye_texture_view());
+
+        // The image is fitted to the new window shape.
+        if self.obstacle_image.is_some() {
+            self.refresh_obstacles(queue);
+        }
+        self.upload_dye_emitters(queue);
+    }
+
+    // The debug views point at the fluid and noise textures, which are
+    // reallocated when the domain changes size.
+    fn refresh_debug_textures(&mut self, device: &wgpu::Device) {
+        self.debug_texture.set_texture_views(
+            device,
+            &[
+                ("fluid", self.fluid.get_velocity_texture_view()),
+                ("noise", self.noise_generator.get_noise_texture_view()),
+                ("pressure", self.fluid.get_pressure_texture_view()),
+                ("divergence", self.fluid.get_divergence_texture_view()),
+                ("vorticity", self.fluid.get_vorticity_texture_view()),
+            ],
+        );
     }
 
     pub fn animate(
@@ -234,6 +589,48 @@ impl Flux {
         self.render(device, queue, encoder, view, screen_viewport);
     }
 
+    /// Advances the simulation to `timestamp` and reads the rendered frame
+    /// back from the offscreen target.
+    ///
+    /// Blocks until the GPU has finished the frame. Native targets only.
+    pub fn animate_to_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        timestamp: f64,
+    ) -> Result<image::RgbaImage> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("flux:animate_to_image"),
+        });
+        self.compute(device, queue, &mut encoder, timestamp);
+        self.render_to_image_with(device, queue, encoder)
+    }
+
+    /// Renders the current state into the offscreen target and reads it back.
+    ///
+    /// Blocks until the GPU has finished the frame. Native targets only.
+    pub fn render_to_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<image::RgbaImage> {
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("flux:render_to_image"),
+        });
+        self.render_to_image_with(device, queue, encoder)
+    }
+
+    fn render_to_image_with(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut encoder: wgpu::CommandEncoder,
+    ) -> Result<image::RgbaImage> {
+        let target = self.offscreen.as_ref().ok_or(Error::NoOffscreenTarget)?;
+        self.render(device, queue, &mut encoder, target.texture_view(), None);
+        target.read_image(device, queue, encoder)
+    }
+
     pub fn compute(
         &mut self,
         device: &wgpu::Device,
@@ -248,6 +645,147 @@ impl Flux {
         );
 
         self.last_timestamp = timestamp;
+
+        self.advance(device, queue, encoder, timestep, MAX_FLUID_STEPS_PER_FRAME);
+    }
+
+    /// Advances the simulation by `n_frames` frames of exactly `timestep`
+    /// seconds each, independent of the host clock.
+    ///
+    /// Unlike `compute`, the timestep isn't clamped, every fluid step it
+    /// covers is run, and each frame is submitted separately, so every frame
+    /// sees its own uniforms. Given the same `Settings.seed`, the same
+    /// sequence of calls produces the same frames. A `timestep` of
+    /// `1.0 / fluid_frame_rate` runs exactly one fluid update per frame.
+    ///
+    /// Each frame waits for the GPU, so the substep counts and blow-up
+    /// detection don't depend on how quickly the fluid speed is read back.
+    /// Fails if waiting for the GPU does.
+    ///
+    /// The host clock used by `compute` is left untouched.
+    pub fn step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        n_frames: u32,
+        timestep: f32,
+    ) -> Result<()> {
+        for _ in 0..n_frames {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("flux:step"),
+            });
+            self.advance(device, queue, &mut encoder, timestep, u32::MAX);
+            queue.submit(Some(encoder.finish()));
+            device
+                .poll(wgpu::PollType::wait_indefinitely())
+                .map_err(|err| Error::Readback(err.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// What the simulation did on the last frame. See `DebugStats`.
+    pub fn debug_stats(&self) -> DebugStats {
+        DebugStats {
+            substeps: self.substeps,
+            max_speed: self.fluid.max_speed(),
+        }
+    }
+
+    /// Captures the simulation state: the fluid fields and speed
+    /// measurements, the lines, the noise offsets, the random number
+    /// generator, the substep count and the animation timers.
+    ///
+    /// Blocks until the GPU has finished reading back. Native targets only.
+    pub fn snapshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<snapshot::Snapshot> {
+        Ok(snapshot::Snapshot {
+            elapsed_time: self.elapsed_time,
+            fluid_frame_time: self.fluid_frame_time,
+            substeps: self.substeps,
+            fluid: self.fluid.snapshot(device, queue)?,
+            lines: self.lines.snapshot(device, queue)?,
+            noise: self.noise_generator.snapshot(),
+            rng: self.rng.snapshot(),
+        })
+    }
+
+    /// How well the last fluid step solved for pressure, as the residual
+    /// relative to that of a zero pressure field. Useful for comparing
+    /// `Settings::pressure_solver` configurations.
+    ///
+    /// Blocks until the GPU has finished reading back. Native targets only.
+    pub fn pressure_residual(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<f32> {
+        self.fluid.relative_pressure_residual(device, queue)
+    }
+
+    /// Restores a snapshot taken with `snapshot`.
+    ///
+    /// The instance must have the same settings and size as the one the
+    /// snapshot was taken from. A mismatch is reported as an error, and may
+    /// leave the state partially restored. The host clock is left untouched.
+    ///
+    /// Stepped with `step`, the restored instance produces the same frames as
+    /// the original did after the snapshot was taken.
+    pub fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        snapshot: &snapshot::Snapshot,
+    ) -> Result<()> {
+        if snapshot.substeps == 0 {
+            return Err(Error::Snapshot("the substep count is zero".to_string()));
+        }
+
+        self.fluid.restore(device, queue, &snapshot.fluid)?;
+        self.lines.restore(queue, &snapshot.lines)?;
+        self.noise_generator.restore(&snapshot.noise)?;
+        self.rng.restore(&snapshot.rng);
+
+        self.elapsed_time = snapshot.elapsed_time;
+        self.fluid_frame_time = snapshot.fluid_frame_time;
+        self.substeps = snapshot.substeps;
+        self.fluid
+            .set_substeps(queue, &self.settings, self.substeps);
+
+        Ok(())
+    }
+
+    /// Starts a new scene without rebuilding any pipelines: the fluid and
+    /// dye are zeroed, the lines grow back from scratch, the noise gets new
+    /// random offsets and the animation timers restart. Nothing from the old
+    /// flow carries over: queued pointer impulses are dropped, and the
+    /// previous step's velocity and the measured fluid speed are cleared.
+    ///
+    /// `seed` works like `Settings.seed`: the same seed gives the same
+    /// scene as a new instance created with it, and `None` picks a random
+    /// one. Obstacles, dye emitters and the host clock are left untouched.
+    pub fn reset(&mut self, queue: &wgpu::Queue, seed: Option<String>) {
+        self.rng = rng::Rng::from_seed(&seed);
+
+        self.impulses.clear();
+        self.fluid.clear(queue);
+        self.lines.reset_lines(queue, &self.grid);
+        self.noise_generator.reset(&mut self.rng);
+
+        self.elapsed_time = 0.0;
+        self.fluid_frame_time = 0.0;
+        self.substeps = 1;
+    }
+
+    // Advance the noise, fluid and lines by `timestep` seconds, running at
+    // most `max_fluid_steps` fluid steps.
+    fn advance(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        timestep: f32,
+        max_fluid_steps: u32,
+    ) {
         self.elapsed_time += timestep;
         self.fluid_frame_time += timestep;
 
@@ -257,10 +795,49 @@ impl Flux {
             self.elapsed_time = timer_overflow;
         }
 
+        // Extreme settings can make the fluid blow up, after which the lines
+        // vanish for good. Start the fluid over instead.
+        self.fluid.poll_speed();
+        if let Some(max_speed) = self.fluid.max_speed() {
+            let fluid_size = self.fluid.get_fluid_size();
+            if render::speed::has_blown_up(max_speed, self.settings.fluid_timestep, fluid_size) {
+                log::warn!(
+                    "The fluid blew up, with a top speed of {}. Resetting the fluid and lines.",
+                    max_speed
+                );
+                self.fluid.clear(queue);
+                self.lines.reset_lines(queue, &self.grid);
+            }
+        }
+
+        // Split the fluid steps so the fastest fluid crosses at most `cfl`
+        // cells per substep. The speed comes from an earlier frame.
+        self.substeps = match self.settings.adaptive_timestep {
+            Some(adaptive) => {
+                let max_speed = self.fluid.max_speed().unwrap_or(0.0);
+                render::speed::substep_count(max_speed, self.settings.fluid_timestep, &adaptive)
+            }
+            None => 1,
+        };
+        self.fluid
+            .set_substeps(queue, &self.settings, self.substeps);
+
         let fluid_update_interval = 1.0 / self.settings.fluid_frame_rate;
+        let mut fluid_steps = 0;
         while self.fluid_frame_time >= fluid_update_interval {
+            if fluid_steps == max_fluid_steps {
+                // Drop the steps we're too far behind on, but keep the
+                // leftover time, so the lines stay in step.
+                self.fluid_frame_time %= fluid_update_interval;
+                break;
+            }
+            fluid_steps += 1;
+
+            self.fluid.save_previous_velocity(encoder);
             self.noise_generator
                 .update_buffers(queue, self.settings.fluid_timestep);
+            let aspect = self.logical_size.width as f32 / self.logical_size.height as f32;
+            let splat_impulses = self.impulses.prepare(queue, aspect);
 
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: Some("flux::compute"),
@@ -269,28 +846,69 @@ impl Flux {
 
             self.noise_generator.generate(&mut cpass);
 
-            self.fluid.advect_forward(queue, &mut cpass);
-            self.fluid.advect_reverse(queue, &mut cpass);
-            self.fluid.adjust_advection(&mut cpass);
-            self.fluid.diffuse(&mut cpass);
+            // The forces are applied once per step, each scaled by the full
+            // timestep.
+            for substep in 0..self.substeps {
+                match self.settings.advection {
+                    settings::Advection::SemiLagrangian => {
+                        self.fluid.advect_semi_lagrangian(&mut cpass);
+                    }
+                    settings::Advection::MacCormack => {
+                        self.fluid.advect_forward(queue, &mut cpass);
+                        self.fluid.advect_reverse(queue, &mut cpass);
+                        self.fluid.adjust_advection(&mut cpass);
+                    }
+                    settings::Advection::Bfecc => {
+                        self.fluid.advect_forward(queue, &mut cpass);
+                        self.fluid.advect_reverse(queue, &mut cpass);
+                        self.fluid.adjust_bfecc_advection(&mut cpass);
+                    }
+                }
+                self.fluid.diffuse(&mut cpass);
 
-            let velocity_bind_group = self.fluid.get_write_velocity_bind_group();
-            self.noise_generator.inject_noise_into(
-                &mut cpass,
-                velocity_bind_group,
-                self.fluid.get_fluid_size(),
-            );
+                if substep == 0 {
+                    let velocity_bind_group = self.fluid.get_write_velocity_bind_group();
+                    self.noise_generator.inject_noise_into(
+                        &mut cpass,
+                        velocity_bind_group,
+                        self.fluid.get_fluid_size(),
+                    );
+
+                    if splat_impulses {
+                        let velocity_bind_group = self.fluid.get_write_velocity_bind_group();
+                        self.impulses.splat_into(
+                            &mut cpass,
+                            velocity_bind_group,
+                            self.fluid.get_fluid_size(),
+                        );
+                    }
+                }
 
-            self.fluid.calculate_divergence(&mut cpass);
-            self.fluid.solve_pressure(queue, &mut cpass);
-            self.fluid.subtract_gradient(&mut cpass);
+                self.fluid.calculate_vorticity(&mut cpass);
+                self.fluid.confine_vorticity(&mut cpass);
+
+                self.fluid.calculate_divergence(&mut cpass);
+                self.fluid.solve_pressure(queue, &mut cpass);
+                self.fluid.subtract_gradient(&mut cpass);
+                self.fluid.advect_dye(&mut cpass);
+            }
 
             self.fluid_frame_time -= fluid_update_interval;
         }
 
+        self.fluid.measure_speed(encoder);
+
         {
-            self.lines
-                .tick_line_uniforms(device, queue, timestep, self.elapsed_time);
+            // The time left over is how far the display has got towards the
+            // next fluid step.
+            let velocity_blend = (self.fluid_frame_time / fluid_update_interval).clamp(0.0, 1.0);
+            self.lines.tick_line_uniforms(
+                device,
+                queue,
+                timestep,
+                self.elapsed_time,
+                velocity_blend,
+            );
 
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: Some("flux::place_lines"),
@@ -342,6 +960,17 @@ impl Flux {
                     self.lines.draw_lines(&mut rpass);
                     self.lines.draw_endpoints(&mut rpass);
                 }
+                Dye => {
+                    let view_transform = screen_viewport
+                        .map(|ref sv| {
+                            render::ViewTransform::from_screen_viewport(&self.physical_size, sv)
+                        })
+                        .unwrap_or_default();
+                    self.lines.set_view_transform(queue, view_transform);
+                    self.lines.draw_dye(&mut rpass);
+                    self.lines.draw_lines(&mut rpass);
+                    self.lines.draw_endpoints(&mut rpass);
+                }
                 DebugNoise => {
                     self.debug_texture.draw_texture(device, &mut rpass, "noise");
                 }
@@ -356,6 +985,10 @@ impl Flux {
                     self.debug_texture
                         .draw_texture(device, &mut rpass, "divergence");
                 }
+                DebugVorticity => {
+                    self.debug_texture
+                        .draw_texture(device, &mut rpass, "vorticity");
+                }
             };
         }
 
@@ -363,21 +996,205 @@ impl Flux {
     }
 }
 
-// #[derive(Debug)]
-// pub enum Problem {
-//     ReadSettings(String),
-//     ReadImage(std::io::Error),
-//     DecodeColorTexture(image::ImageError),
-//     Render(render::Problem),
-// }
-//
-// impl fmt::Display for Problem {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         match self {
-//             Problem::ReadSettings(msg) => write!(f, "{}", msg),
-//             Problem::ReadImage(msg) => write!(f, "{}", msg),
-//             Problem::DecodeColorTexture(msg) => write!(f, "Failed to decode image: {}", msg),
-//             Problem::Render(render_msg) => write!(f, "{}", render_msg),
-//         }
-//     }
-// }
+// Limits Flux needs beyond what every WebGPU device provides, or that a
+// downlevel device may lack.
+fn check_device_limits(limits: &wgpu::Limits) -> Result<()> {
+    let requirements = [
+        (
+            "max_storage_buffers_per_shader_stage",
+            5,
+            limits.max_storage_buffers_per_shader_stage,
+        ),
+        (
+            "max_storage_textures_per_shader_stage",
+            2,
+            limits.max_storage_textures_per_shader_stage,
+        ),
+        (
+            "max_compute_invocations_per_workgroup",
+            16 * 16,
+            limits.max_compute_invocations_per_workgroup,
+        ),
+        (
+            "max_compute_workgroup_size_x",
+            64,
+            limits.max_compute_workgroup_size_x,
+        ),
+        (
+            "max_compute_workgroup_size_y",
+            16,
+            limits.max_compute_workgroup_size_y,
+        ),
+        // Zero on devices without compute shaders.
+        (
+            "max_compute_workgroups_per_dimension",
+            1,
+            limits.max_compute_workgroups_per_dimension,
+        ),
+    ];
+
+    for (limit, required, supported) in requirements {
+        if supported < required {
+            return Err(Error::UnsupportedDevice {
+                limit,
+                required,
+                supported,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Native backends resolve error scopes immediately, so a single poll is enough.
+// On the web the result arrives asynchronously; by then it has been dropped and
+// errors fall through to the device's uncaptured error handler, as before.
+fn pop_error_scope_now(error_scope: wgpu::ErrorScopeGuard) -> Option<wgpu::Error> {
+    let future = std::pin::pin!(error_scope.pop());
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    match std::future::Future::poll(future, &mut cx) {
+        std::task::Poll::Ready(err) => err,
+        std::task::Poll::Pending => None,
+    }
+}
+
+// The image of a `ColorMode::ImageFile` or `ColorMode::ImagePalette`, the
+// options it's prepared with, and whether only its palette is used. Switching
+// between the two modes loads the image again.
+type ColorImageSource = (std::path::PathBuf, settings::ImageOptions, bool);
+
+fn color_image_source(color_mode: &settings::ColorMode) -> Option<ColorImageSource> {
+    match color_mode {
+        settings::ColorMode::ImageFile { path, options } => Some((path.clone(), *options, false)),
+        settings::ColorMode::ImagePalette { path, options } => Some((path.clone(), *options, true)),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_color_image((path, options, _): &ColorImageSource) -> Result<Option<image::RgbaImage>> {
+    render::color::Context::read_color_texture(path, options).map(Some)
+}
+
+// On the web, the host fetches the image itself and hands it over with
+// `sample_colors_from_image`.
+#[cfg(target_arch = "wasm32")]
+fn read_color_image(_source: &ColorImageSource) -> Result<Option<image::RgbaImage>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Half a fluid step per frame, so the lines blend between steps.
+    fn step_half_frames(flux: &mut Flux, device: &wgpu::Device, queue: &wgpu::Queue, n: u32) {
+        let timestep = 0.5 / flux.settings.fluid_frame_rate;
+        flux.step(device, queue, n, timestep).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter; run with `cargo test -- --ignored`"]
+    fn resumes_exactly_from_a_snapshot() {
+        let (device, queue, caps) =
+            render::request_test_device().expect("no GPU adapter available");
+        let settings = Arc::new(Settings {
+            seed: Some("snapshot".to_string()),
+            mode: settings::Mode::Dye,
+            fluid_size: 64,
+            // Long enough for the fluid to need several substeps.
+            fluid_timestep: 1.0,
+            adaptive_timestep: Some(settings::AdaptiveTimestep {
+                cfl: 0.1,
+                max_substeps: 4,
+            }),
+            dye_emitters: vec![settings::DyeEmitter {
+                position: [0.5, 0.5],
+                radius: 0.2,
+                color: [1.0, 0.3, 0.1, 1.0],
+                rate: 2.0,
+            }],
+            ..Default::default()
+        });
+        let new_instance = || Flux::new_offscreen(&device, &queue, 320, 180, caps, &settings);
+
+        let mut original = new_instance().unwrap();
+        step_half_frames(&mut original, &device, &queue, 40);
+        let snapshot = original.snapshot(&device, &queue).unwrap();
+        let snapshot = snapshot::Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        // The next frame blends from the previous velocity without a fluid
+        // step, with a speed measurement in flight.
+        assert!(snapshot.substeps > 1);
+        assert!(snapshot.fluid.speed.pending.is_some());
+        assert_ne!(snapshot.fluid.previous_velocity, snapshot.fluid.velocity);
+
+        let mut restored = new_instance().unwrap();
+        restored.restore(&device, &queue, &snapshot).unwrap();
+        assert_eq!(restored.debug_stats(), original.debug_stats());
+        for _ in 0..5 {
+            step_half_frames(&mut original, &device, &queue, 3);
+            step_half_frames(&mut restored, &device, &queue, 3);
+            assert_eq!(
+                restored.snapshot(&device, &queue).unwrap(),
+                original.snapshot(&device, &queue).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter; run with `cargo test -- --ignored`"]
+    fn keeps_the_image_palette_across_updates() {
+        let (device, queue, caps) =
+            render::request_test_device().expect("no GPU adapter available");
+        let path = std::env::temp_dir().join("flux-palette-image.png");
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let settings = Arc::new(Settings {
+            seed: Some("palette".to_string()),
+            color_mode: settings::ColorMode::ImagePalette {
+                path: path.clone(),
+                options: Default::default(),
+            },
+            ..Default::default()
+        });
+
+        let mut flux = Flux::new_offscreen(&device, &queue, 320, 180, caps, &settings).unwrap();
+        // The palette outlives updates, even ones that change how it blends.
+        let updated = Arc::new(Settings {
+            line_width: settings.line_width * 2.0,
+            gradient_color_space: settings::ColorSpace::Oklch,
+            ..(*settings).clone()
+        });
+        let result = flux.update(&device, &queue, &updated);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        step_half_frames(&mut flux, &device, &queue, 20);
+        let image = flux.render_to_image(&device, &queue).unwrap();
+        assert!(image.pixels().any(|pixel| pixel[0] > 0));
+        // Red, rather than the grey of red read as OKLCH.
+        assert!(image
+            .pixels()
+            .all(|pixel| pixel[1] <= pixel[0] / 2 && pixel[2] <= pixel[0] / 2));
+    }
+
+    #[test]
+    fn accepts_default_limits() {
+        assert!(check_device_limits(&wgpu::Limits::default()).is_ok());
+    }
+
+    #[test]
+    fn reports_missing_limits() {
+        // The downlevel defaults only guarantee 4 storage buffers per stage.
+        match check_device_limits(&wgpu::Limits::downlevel_defaults()) {
+            Err(Error::UnsupportedDevice {
+                limit, required, ..
+            }) => {
+                assert_eq!(limit, "max_storage_buffers_per_shader_stage");
+                assert_eq!(required, 5);
+            }
+            other => panic!("expected an unsupported device error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/// Maps fluid cells onto an image fitted inside the view.
+///
+/// The image is centred and keeps its aspect ratio, and the view is zoomed by
+/// `view_scale` like the lines are, so whatever is read from the image lines
+/// up with it as it would appear on screen.
+pub struct ImageFit {
+    image_size: (u32, u32),
+    view_width: f32,
+    view_height: f32,
+    view_scale: f32,
+    scale: f32,
+    offset: [f32; 2],
+}
+
+impl ImageFit {
+    pub fn new(image_size: (u32, u32), view_size: wgpu::Extent3d, view_scale: f32) -> Self {
+        let view_width = view_size.width as f32;
+        let view_height = view_size.height as f32;
+        let scale = (view_width / image_size.0 as f32).min(view_height / image_size.1 as f32);
+
+        Self {
+            image_size,
+            view_width,
+            view_height,
+            view_scale,
+            scale,
+            offset: [
+                0.5 * (view_width - scale * image_size.0 as f32),
+                0.5 * (view_height - scale * image_size.1 as f32),
+            ],
+        }
+    }
+
+    /// The image pixel under the centre of cell `(x, y)` of a `fluid_size`
+    /// grid, or `None` if the cell is outside the image. Rows count up from
+    /// the bottom of the domain.
+    pub fn pixel(&self, fluid_size: wgpu::Extent3d, x: u32, y: u32) -> Option<(u32, u32)> {
+        if self.image_size.0 == 0 || self.image_size.1 == 0 {
+            return None;
+        }
+
+        // From texture space, with y pointing up, to the window.
+        let u = (x as f32 + 0.5) / fluid_size.width as f32;
+        let v = (y as f32 + 0.5) / fluid_size.height as f32;
+        let window_x = (0.5 + (u - 0.5) * self.view_scale) * self.view_width;
+        let window_y = (0.5 - (v - 0.5) * self.view_scale) * self.view_height;
+
+        let image_x = ((window_x - self.offset[0]) / self.scale).floor();
+        let image_y = ((window_y - self.offset[1]) / self.scale).floor();
+        if image_x < 0.0
+            || image_y < 0.0
+            || image_x >= self.image_size.0 as f32
+            || image_y >= self.image_size.1 as f32
+        {
+            return None;
+        }
+
+        Some((image_x as u32, image_y as u32))
+    }
+}
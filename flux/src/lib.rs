@@ -1,8 +1,16 @@
+pub mod color;
+mod dye;
+mod error;
 mod flux;
 mod grid;
+mod image_fit;
+mod obstacle;
 pub mod render;
 mod rng;
 pub mod settings;
+pub mod snapshot;
 
-pub use flux::{BackendCaps, Flux};
+pub use error::{Error, Result};
+pub use flux::{BackendCaps, DebugStats, Flux};
 pub use settings::Settings;
+pub use snapshot::Snapshot;
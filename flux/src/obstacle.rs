@@ -0,0 +1,162 @@
+use crate::image_fit::ImageFit;
+use crate::settings::{MaskChannel, ObstacleMask};
+
+use image::RgbaImage;
+
+/// Rasterizes a mask image into one byte per fluid cell: 255 for solid and 0
+/// for open, in the row order `fluid::Context::set_obstacles` expects.
+///
+/// The image is fitted inside the view; see `ImageFit`. Anything outside the
+/// image is open.
+pub fn rasterize(
+    image: &RgbaImage,
+    mask: &ObstacleMask,
+    fluid_size: wgpu::Extent3d,
+    view_size: wgpu::Extent3d,
+    view_scale: f32,
+) -> Vec<u8> {
+    let fit = ImageFit::new(image.dimensions(), view_size, view_scale);
+    let mut cells = vec![0; (fluid_size.width * fluid_size.height) as usize];
+
+    for y in 0..fluid_size.height {
+        for x in 0..fluid_size.width {
+            let Some((image_x, image_y)) = fit.pixel(fluid_size, x, y) else {
+                continue;
+            };
+
+            let [r, g, b, a] = image.get_pixel(image_x, image_y).0;
+            let value = match mask.channel {
+                MaskChannel::Alpha => a as f32 / 255.0,
+                MaskChannel::Luminance => {
+                    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.0
+                }
+            };
+
+            if (value > mask.threshold) != mask.invert {
+                cells[(y * fluid_size.width + x) as usize] = 255;
+            }
+        }
+    }
+
+    cells
+}
+
+/// Shrinks a grid of cells from `rasterize` to half its resolution, rounding
+/// up, for the multigrid pressure solver. A coarse cell is solid only if all
+/// of the fine cells it covers are.
+pub fn coarsen(cells: &[u8], size: wgpu::Extent3d, coarse_size: wgpu::Extent3d) -> Vec<u8> {
+    let mut coarse_cells = vec![0; (coarse_size.width * coarse_size.height) as usize];
+
+    for y in 0..coarse_size.height {
+        for x in 0..coarse_size.width {
+            let solid = (2 * y..(2 * y + 2).min(size.height)).all(|fine_y| {
+                (2 * x..(2 * x + 2).min(size.width))
+                    .all(|fine_x| cells[(fine_y * size.width + fine_x) as usize] != 0)
+            });
+            if solid {
+                coarse_cells[(y * coarse_size.width + x) as usize] = 255;
+            }
+        }
+    }
+
+    coarse_cells
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    const OPAQUE: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const CLEAR: Rgba<u8> = Rgba([255, 255, 255, 0]);
+
+    #[test]
+    fn marks_opaque_pixels_as_solid() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { OPAQUE } else { CLEAR });
+        let cells = rasterize(
+            &image,
+            &ObstacleMask::default(),
+            extent(4, 2),
+            extent(200, 100),
+            1.0,
+        );
+        assert_eq!(cells, [255, 255, 0, 0, 255, 255, 0, 0]);
+    }
+
+    #[test]
+    fn flips_the_image_into_texture_space() {
+        let image = RgbaImage::from_fn(1, 2, |_, y| if y == 0 { OPAQUE } else { CLEAR });
+        let cells = rasterize(
+            &image,
+            &ObstacleMask::default(),
+            extent(2, 4),
+            extent(100, 200),
+            1.0,
+        );
+        // The top of the image lands in the last rows.
+        assert_eq!(cells, [0, 0, 0, 0, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn inverting_leaves_the_letterbox_open() {
+        // A square image in a wide view covers the middle quarter.
+        let image = RgbaImage::from_pixel(1, 1, CLEAR);
+        let mask = ObstacleMask {
+            invert: true,
+            ..Default::default()
+        };
+        let cells = rasterize(&image, &mask, extent(8, 1), extent(400, 100), 1.0);
+        assert_eq!(cells, [0, 0, 0, 255, 255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn reads_luminance() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([40, 40, 40, 255])
+            }
+        });
+        let mask = ObstacleMask {
+            channel: MaskChannel::Luminance,
+            ..Default::default()
+        };
+        let cells = rasterize(&image, &mask, extent(2, 1), extent(200, 100), 1.0);
+        assert_eq!(cells, [255, 0]);
+    }
+
+    #[test]
+    fn zooms_with_the_view() {
+        // Zoomed in 2x, the view only shows the middle half of the domain, so
+        // the outer cells fall outside the image.
+        let image = RgbaImage::from_pixel(1, 1, OPAQUE);
+        let cells = rasterize(
+            &image,
+            &ObstacleMask::default(),
+            extent(4, 1),
+            extent(100, 100),
+            2.0,
+        );
+        assert_eq!(cells, [0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn coarsens_only_fully_solid_blocks() {
+        #[rustfmt::skip]
+        let cells = [
+            255, 255, 255, 0,   255,
+            255, 255, 255, 255, 255,
+        ];
+        let coarse = coarsen(&cells, extent(5, 2), extent(3, 1));
+        assert_eq!(coarse, [255, 0, 255]);
+    }
+}
@@ -1,20 +1,9 @@
-use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
-
-pub enum Problem {
-    ReadImage(std::io::Error),
-    DecodeColorTexture(image::ImageError),
-}
+use crate::color::{oklab_to_oklch, srgb_to_oklab};
+use crate::error::Result;
+use crate::settings::{ImageFitMode, ImageOptions, ResampleFilter};
 
-impl std::fmt::Display for Problem {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Problem::ReadImage(err) => write!(f, "Failed to read image: {}", err),
-            Problem::DecodeColorTexture(err) => {
-                write!(f, "Failed to decode color texture: {}", err)
-            }
-        }
-    }
-}
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
 
 pub struct Context {
     #[allow(dead_code)]
@@ -23,24 +12,25 @@ pub struct Context {
 }
 
 impl Context {
-    // pub fn load_color_texture_from_file(device: &wgpu::Device, queue: &wgpu::Queue, path: &path::PathBuf) -> Result<Context, Problem> {
-    //     std::fs::read(path)
-    //         .map_err(Problem::ReadImage)
-    //         .and_then(|ref encoded_bytes| Self::load_color_texture(device, queue, encoded_bytes))
-    //         .map_err(|err| {
-    //             log::error!("Failed to load image from {}: {}", path.display(), err);
-    //             err
-    //         })
-    // }
-
-    pub fn decode_color_texture(encoded_bytes: &[u8]) -> Result<RgbaImage, Problem> {
+    /// Reads and decodes the image at `path`. Not available on the web, where
+    /// the host fetches images itself.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_color_texture(path: &std::path::Path, options: &ImageOptions) -> Result<RgbaImage> {
+        log::debug!("Reading image from {}", path.display());
+
+        let encoded_bytes =
+            std::fs::read(path).map_err(|source| crate::error::Error::ReadImage {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Self::decode_color_texture(&encoded_bytes, options)
+    }
+
+    pub fn decode_color_texture(encoded_bytes: &[u8], options: &ImageOptions) -> Result<RgbaImage> {
         log::debug!("Decoding image");
 
-        let mut img =
-            image::load_from_memory(encoded_bytes).map_err(Problem::DecodeColorTexture)?;
-        if u32::max(img.width(), img.height()) > 640 {
-            img = img.resize(640, 400, image::imageops::FilterType::Nearest);
-        }
+        let img = image::load_from_memory(encoded_bytes)?;
+        let img = preprocess_image(img, options);
 
         log::debug!(
             "Uploading image (width: {}, height: {})",
@@ -48,8 +38,142 @@ impl Context {
             img.height()
         );
 
-        Ok(increase_black_level(&img, 25).to_rgba8())
+        Ok(img)
+    }
+}
+
+/// Prepares a decoded image for the lines to sample colors from. See
+/// `ImageOptions` for the steps.
+pub fn preprocess_image(mut img: DynamicImage, options: &ImageOptions) -> RgbaImage {
+    let (width, height) = (options.width, options.height);
+    let filter = filter_type(options.filter);
+    img = match options.fit {
+        ImageFitMode::Contain if img.width() > width || img.height() > height => {
+            img.resize(width, height, filter)
+        }
+        ImageFitMode::Contain => img,
+        ImageFitMode::Cover => img.resize_to_fill(width, height, filter),
+        ImageFitMode::Stretch => img.resize_exact(width, height, filter),
+    };
+
+    if options.blur > 0.0 {
+        img = img.blur(options.blur);
+    }
+
+    let mut img = img.to_rgba8();
+    adjust_colors(&mut img, options);
+
+    let threshold = (options.black_level * 255.0).round() as u8;
+    increase_black_level(&DynamicImage::ImageRgba8(img), threshold).to_rgba8()
+}
+
+fn filter_type(filter: ResampleFilter) -> FilterType {
+    match filter {
+        ResampleFilter::Nearest => FilterType::Nearest,
+        ResampleFilter::Triangle => FilterType::Triangle,
+        ResampleFilter::CatmullRom => FilterType::CatmullRom,
+        ResampleFilter::Gaussian => FilterType::Gaussian,
+        ResampleFilter::Lanczos3 => FilterType::Lanczos3,
+    }
+}
+
+// Applies the contrast, saturation and gamma, in that order, to the sRGB
+// values.
+fn adjust_colors(img: &mut RgbaImage, options: &ImageOptions) {
+    if options.contrast == 1.0 && options.saturation == 1.0 && options.gamma == 1.0 {
+        return;
     }
+
+    for Rgba(pixel) in img.pixels_mut() {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]]
+            .map(|value| (value as f32 / 255.0 - 0.5) * options.contrast + 0.5);
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let rgb = [r, g, b].map(|value| {
+            let value = luminance + (value - luminance) * options.saturation;
+            let value = value.clamp(0.0, 1.0).powf(1.0 / options.gamma);
+            (value * 255.0).round() as u8
+        });
+        pixel[..3].copy_from_slice(&rgb);
+    }
+}
+
+/// Picks the `count` dominant colors of `image` by median cut, ordered by
+/// hue so that neighbouring colors blend nicely around a color wheel.
+///
+/// Transparent pixels are ignored. An image with fewer distinct colors gives
+/// fewer, and a fully transparent one gives none.
+pub fn extract_palette(image: &RgbaImage, count: usize) -> Vec<[f32; 4]> {
+    let pixels = image
+        .pixels()
+        .filter(|Rgba([_, _, _, a])| *a > 0)
+        .map(|Rgba([r, g, b, _])| [*r, *g, *b])
+        .collect::<Vec<_>>();
+    if pixels.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        // Split the box spanning the widest range, weighted by how many pixels
+        // it holds, so the palette goes to the colors that cover the most of
+        // the image rather than to a few stray pixels.
+        let Some((index, channel, _)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (index, channel, range as usize * pixels.len())
+            })
+            .filter(|(_, _, weight)| *weight > 0)
+            .max_by_key(|(_, _, weight)| *weight)
+        else {
+            break;
+        };
+
+        // Split at the median, keeping equal values on the same side.
+        let pixels = &mut boxes[index];
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let median = pixels[pixels.len() / 2][channel];
+        let split = match pixels.partition_point(|pixel| pixel[channel] < median) {
+            0 => pixels.partition_point(|pixel| pixel[channel] <= median),
+            split => split,
+        };
+        let upper = pixels.split_off(split);
+        boxes.push(upper);
+    }
+
+    let mut palette = boxes
+        .iter()
+        .map(|pixels| {
+            let mut sum = [0u64; 3];
+            for pixel in pixels {
+                for (total, &value) in sum.iter_mut().zip(pixel) {
+                    *total += value as u64;
+                }
+            }
+            let [r, g, b] = sum.map(|total| total as f32 / pixels.len() as f32 / 255.0);
+            [r, g, b, 1.0]
+        })
+        .collect::<Vec<_>>();
+
+    palette.sort_by(|a, b| hue(a).total_cmp(&hue(b)));
+    palette
+}
+
+// The channel with the widest range of values, and that range.
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|pixel| pixel[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn hue([r, g, b, _]: &[f32; 4]) -> f32 {
+    oklab_to_oklch(srgb_to_oklab([*r, *g, *b]))[2].rem_euclid(std::f32::consts::TAU)
 }
 
 fn increase_black_level(img: &DynamicImage, threshold: u8) -> DynamicImage {
@@ -121,3 +245,149 @@ pub fn load_color_texture(
 
     texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(width: u32, height: u32, fit: ImageFitMode) -> ImageOptions {
+        ImageOptions {
+            width,
+            height,
+            fit,
+            black_level: 0.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fits_images_to_the_target_size() {
+        let image = || DynamicImage::new_rgba8(200, 100);
+        let size = |fit| preprocess_image(image(), &options(50, 50, fit)).dimensions();
+        assert_eq!(size(ImageFitMode::Contain), (50, 25));
+        assert_eq!(size(ImageFitMode::Cover), (50, 50));
+        assert_eq!(size(ImageFitMode::Stretch), (50, 50));
+
+        // Small images are only enlarged to cover or stretch.
+        let large = |fit| preprocess_image(image(), &options(400, 400, fit)).dimensions();
+        assert_eq!(large(ImageFitMode::Contain), (200, 100));
+        assert_eq!(large(ImageFitMode::Cover), (400, 400));
+    }
+
+    #[test]
+    fn crops_the_centre_to_cover() {
+        // Red, green and blue thirds, left to right.
+        let image = RgbaImage::from_fn(3, 1, |x, _| {
+            let mut pixel = Rgba([0, 0, 0, 255]);
+            pixel.0[x as usize] = 255;
+            pixel
+        });
+
+        let covered = preprocess_image(
+            DynamicImage::ImageRgba8(image),
+            &options(1, 1, ImageFitMode::Cover),
+        );
+        assert_eq!(covered.get_pixel(0, 0), &Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn adjusts_colors() {
+        let image =
+            || DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([200, 50, 50, 128])));
+        let adjust = |options: ImageOptions| *preprocess_image(image(), &options).get_pixel(0, 0);
+
+        // The defaults only lift the black level.
+        assert_eq!(adjust(ImageOptions::default()), Rgba([200, 50, 50, 128]));
+
+        let Rgba([r, g, b, a]) = adjust(ImageOptions {
+            saturation: 0.0,
+            ..Default::default()
+        });
+        assert_eq!((r, a), (g, 128));
+        assert_eq!(g, b);
+
+        let Rgba([r, g, _, _]) = adjust(ImageOptions {
+            contrast: 2.0,
+            ..Default::default()
+        });
+        assert_eq!((r, g), (255, 0));
+
+        let Rgba([r, g, _, _]) = adjust(ImageOptions {
+            gamma: 2.0,
+            ..Default::default()
+        });
+        assert!(r > 200 && g > 50);
+    }
+
+    #[test]
+    fn lifts_the_black_level() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([10, 0, 80, 255])));
+        let options = ImageOptions {
+            black_level: 0.2,
+            ..Default::default()
+        };
+        // Only pixels dark in every channel are lifted.
+        assert_eq!(
+            preprocess_image(image, &options).get_pixel(0, 0),
+            &Rgba([10, 0, 80, 255])
+        );
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([10, 0, 20, 255])));
+        assert_eq!(
+            preprocess_image(image, &options).get_pixel(0, 0),
+            &Rgba([51, 51, 51, 255])
+        );
+    }
+
+    #[test]
+    fn reports_unreadable_images() {
+        let missing = std::env::temp_dir().join("flux-missing-image.png");
+        assert!(matches!(
+            Context::read_color_texture(&missing, &ImageOptions::default()),
+            Err(crate::Error::ReadImage { path, .. }) if path == missing
+        ));
+
+        let corrupt = std::env::temp_dir().join("flux-corrupt-image.png");
+        std::fs::write(&corrupt, b"not an image").unwrap();
+        let result = Context::read_color_texture(&corrupt, &ImageOptions::default());
+        std::fs::remove_file(&corrupt).unwrap();
+        assert!(matches!(result, Err(crate::Error::DecodeImage(_))));
+    }
+
+    #[test]
+    fn extracts_the_dominant_colors() {
+        // Mostly two shades of red, with a band of blue.
+        let image = RgbaImage::from_fn(10, 10, |x, y| match y {
+            0..=1 => Rgba([0, 0, 255, 255]),
+            _ => Rgba([200 + (x as u8 % 2) * 20, 0, 0, 255]),
+        });
+
+        // The shades of red are averaged.
+        assert_eq!(
+            extract_palette(&image, 2),
+            vec![[210.0 / 255.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]]
+        );
+
+        // A bigger palette tells them apart.
+        let palette = extract_palette(&image, 3);
+        assert_eq!(palette.len(), 3);
+        assert!(palette.contains(&[200.0 / 255.0, 0.0, 0.0, 1.0]));
+        assert!(palette.contains(&[220.0 / 255.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn stops_at_the_distinct_colors() {
+        let image = RgbaImage::from_fn(4, 4, |x, _| match x {
+            0 => Rgba([0, 0, 0, 0]),
+            1 => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 255, 255]),
+        });
+
+        // Ordered by hue, and the transparent pixels are skipped.
+        assert_eq!(
+            extract_palette(&image, 8),
+            vec![[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]]
+        );
+        assert!(extract_palette(&RgbaImage::new(2, 2), 8).is_empty());
+    }
+}
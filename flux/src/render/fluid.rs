@@ -1,9 +1,13 @@
+use crate::error::{Error, Result};
 use crate::grid;
-use crate::settings::{self, Settings};
+use crate::obstacle;
+use crate::settings::{self, Settings, MAX_DYE_EMITTERS};
+use crate::snapshot;
 use crate::BackendCaps;
 
-use super::downgrade_float_storage;
+use super::{address_mode, downgrade_float_storage, readback, speed};
 
+use bytemuck::Zeroable;
 use std::borrow::Cow;
 use std::sync::{Arc, Mutex};
 use wgpu::util::DeviceExt;
@@ -18,157 +22,162 @@ struct Direction {
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct FluidUniforms {
-    timestep: f32,       // 0
-    dissipation: f32,    // 4
-    alpha: f32,          // 8
-    r_beta: f32,         // 12
-    center_factor: f32,  // 16
-    stencil_factor: f32, // 20
-    _padding0: u32,      // 24
-    _padding1: u32,      // 28
-                         // roundUp(4, 24) = 24 -> roundUp to 32
+    timestep: f32,        // 0
+    dissipation: f32,     // 4
+    alpha: f32,           // 8
+    r_beta: f32,          // 12
+    center_factor: f32,   // 16
+    stencil_factor: f32,  // 20
+    dye_dissipation: f32, // 24
+    vorticity: f32,       // 28
+    boundary: u32,        // 32
+    _padding: [u32; 3],   // 36
+                          // roundUp(16, 48) = 48
 }
 
 impl FluidUniforms {
-    pub fn new(_size: &wgpu::Extent3d, settings: &Settings) -> Self {
+    // Each fluid step runs the passes `substeps` times, so each pass advances
+    // by a fraction of the timestep.
+    pub fn new(_size: &wgpu::Extent3d, settings: &Settings, substeps: u32) -> Self {
+        let timestep = settings.fluid_timestep / substeps as f32;
+
         // dx^2 / (rho * dt)
-        let center_factor = 1.0 / (settings.viscosity * settings.fluid_timestep);
+        let center_factor = 1.0 / (settings.viscosity * timestep);
         let stencil_factor = 1.0 / (4.0 + center_factor);
 
         FluidUniforms {
-            timestep: settings.fluid_timestep,
+            timestep,
             dissipation: settings.velocity_dissipation,
             alpha: -1.0,
             r_beta: 0.25,
             center_factor,
             stencil_factor,
-            _padding0: 0,
-            _padding1: 0,
+            dye_dissipation: settings.dye_dissipation,
+            vorticity: settings.vorticity,
+            boundary: settings.boundary.into(),
+            _padding: [0; 3],
         }
     }
 }
 
-pub struct Context {
-    fluid_size: [f32; 2],
-    fluid_size_3d: wgpu::Extent3d,
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DyeEmitter {
+    color: [f32; 4],    // 0
+    position: [f32; 2], // 16
+    radius: f32,        // 24
+    rate: f32,          // 28
+                        // roundUp(16, 32) = 32
+}
 
-    diffusion_iterations: u32,
-    pressure_mode: settings::PressureMode,
-    pressure_iterations: u32,
+impl DyeEmitter {
+    /// `position` is in texture space, `radius` is a fraction of the domain
+    /// height, and `color` is linear and premultiplied.
+    pub fn new(position: [f32; 2], radius: f32, color: [f32; 4], rate: f32) -> Self {
+        Self {
+            color,
+            position,
+            radius,
+            rate,
+        }
+    }
+}
 
-    fluid_uniforms: FluidUniforms,
-    fluid_uniform_buffer: wgpu::Buffer,
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DyeUniforms {
+    count: u32,                               // 0
+    aspect: f32,                              // 4
+    timestep: f32,                            // 8
+    _padding: f32,                            // 12
+    emitters: [DyeEmitter; MAX_DYE_EMITTERS], // 16
+}
+
+// The multigrid V-cycle. Each level is smoothed with a few damped Jacobi
+// sweeps on the way down and back up, and the coarsest is swept until the
+// error is mostly gone. Levels stop halving at `MULTIGRID_MIN_SIZE` cells.
+const MULTIGRID_JACOBI_WEIGHT: f64 = 0.8;
+const MULTIGRID_SMOOTHING_SWEEPS: u32 = 2;
+const MULTIGRID_COARSEST_SWEEPS: u32 = 16;
+const MULTIGRID_MIN_SIZE: u32 = 4;
+
+/// Bind group layouts for the size-dependent fluid resources, kept so that
+/// the fields can be rebuilt on resize.
+struct Layouts {
+    uniform: wgpu::BindGroupLayout,
+    velocity: wgpu::BindGroupLayout,
+    advection: wgpu::BindGroupLayout,
+    adjust_advection: wgpu::BindGroupLayout,
+    divergence: wgpu::BindGroupLayout,
+    divergence_sample: wgpu::BindGroupLayout,
+    pressure: wgpu::BindGroupLayout,
+    vorticity: wgpu::BindGroupLayout,
+    multigrid_clear: wgpu::BindGroupLayout,
+}
 
-    _velocity_textures: [wgpu::Texture; 2],
+/// The textures and bind groups sized to the fluid domain.
+struct Fields {
+    velocity_textures: [wgpu::Texture; 2],
     velocity_texture_views: [wgpu::TextureView; 2],
+    // The velocity before the latest fluid step, for interpolating between
+    // steps.
+    previous_velocity_texture: wgpu::Texture,
+    previous_velocity_texture_view: wgpu::TextureView,
     _advection_forward_texture: wgpu::Texture,
     advection_forward_texture_view: wgpu::TextureView,
     _advection_reverse_texture: wgpu::Texture,
     _advection_reverse_texture_view: wgpu::TextureView,
-    _divergence_texture: wgpu::Texture,
+    divergence_texture: wgpu::Texture,
     divergence_texture_view: wgpu::TextureView,
     pressure_textures: [wgpu::Texture; 2],
     pressure_texture_views: [wgpu::TextureView; 2],
+    obstacle_texture: wgpu::Texture,
+    obstacle_texture_view: wgpu::TextureView,
+    // The dye always ends a step in `dye_textures[0]`, so it needs no
+    // ping-pong index.
+    dye_textures: [wgpu::Texture; 2],
+    dye_texture_views: [wgpu::TextureView; 2],
+    _dye_advection_forward_texture: wgpu::Texture,
+    _dye_advection_reverse_texture: wgpu::Texture,
+    _vorticity_texture: wgpu::Texture,
+    vorticity_texture_view: wgpu::TextureView,
 
-    velocity_bind_groups: [wgpu::BindGroup; 2],
     uniform_bind_group: wgpu::BindGroup,
+    velocity_bind_groups: [wgpu::BindGroup; 2],
     advection_forward_bind_group: wgpu::BindGroup,
     advection_reverse_bind_group: wgpu::BindGroup,
-    advection_forward_direction_bind_group: wgpu::BindGroup,
-    advection_reverse_direction_bind_group: wgpu::BindGroup,
     advection_reverse_input_bind_group: wgpu::BindGroup,
     adjust_advection_bind_group: wgpu::BindGroup,
+    // Semi-Lagrangian advection writes straight into the other velocity
+    // texture.
+    advection_velocity_bind_groups: [wgpu::BindGroup; 2],
+    bfecc_correction_bind_group: wgpu::BindGroup,
+    bfecc_advection_bind_groups: [wgpu::BindGroup; 2],
     divergence_bind_group: wgpu::BindGroup,
     divergence_sample_bind_group: wgpu::BindGroup,
     pressure_bind_groups: [wgpu::BindGroup; 2],
-
-    advection_pipeline: wgpu::ComputePipeline,
-    adjust_advection_pipeline: wgpu::ComputePipeline,
-    diffusion_pipeline: wgpu::ComputePipeline,
-    divergence_pipeline: wgpu::ComputePipeline,
-    pressure_pipeline: wgpu::ComputePipeline,
-    subtract_gradient_pipeline: wgpu::ComputePipeline,
-
-    last_pressure_index: Arc<Mutex<usize>>,
-    last_velocity_index: Arc<Mutex<usize>>,
+    dye_advection_forward_bind_group: wgpu::BindGroup,
+    dye_advection_reverse_bind_group: wgpu::BindGroup,
+    dye_adjust_advection_bind_group: wgpu::BindGroup,
+    dye_adjust_bind_group: wgpu::BindGroup,
+    dye_inject_bind_group: wgpu::BindGroup,
+    vorticity_bind_group: wgpu::BindGroup,
+    vorticity_sample_bind_group: wgpu::BindGroup,
+
+    // Coarser copies of the pressure problem, finest first.
+    multigrid_levels: Vec<MultigridLevel>,
 }
 
-impl Context {
-    pub fn update(
-        &mut self,
-        _device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        scaling_ratio: grid::ScalingRatio,
-        settings: &Arc<Settings>,
-    ) {
-        let (width, height) = (
-            scaling_ratio.rounded_x() * settings.fluid_size,
-            scaling_ratio.rounded_y() * settings.fluid_size,
-        );
-        let size = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-
-        // Resize the fluid texture if necessary
-        if self.fluid_size_3d != size {
-            self.fluid_size = [width as f32, height as f32];
-            self.fluid_size_3d = size;
-            // self.resize_fluid_texture(width, height).unwrap();
-        }
-
-        // Update fluid settings needed on the CPU side
-        self.diffusion_iterations = settings.diffusion_iterations;
-        self.pressure_mode = settings.pressure_mode;
-        self.pressure_iterations = settings.pressure_iterations;
-
-        // Update uniforms
-        self.fluid_uniforms = FluidUniforms::new(&size, settings);
-        queue.write_buffer(
-            &self.fluid_uniform_buffer,
-            0,
-            bytemuck::cast_slice(&[self.fluid_uniforms]),
-        );
-    }
-
-    pub fn new(
+impl Fields {
+    fn new(
         device: &wgpu::Device,
-        _queue: &wgpu::Queue,
-        scaling_ratio: grid::ScalingRatio,
-        caps: BackendCaps,
-        settings: &Arc<Settings>,
+        size: wgpu::Extent3d,
+        pressure_format: wgpu::TextureFormat,
+        layouts: &Layouts,
+        uniform_buffer: &wgpu::Buffer,
+        linear_sampler: &wgpu::Sampler,
+        nearest_sampler: &wgpu::Sampler,
     ) -> Self {
-        let (width, height) = (
-            scaling_ratio.rounded_x() * settings.fluid_size,
-            scaling_ratio.rounded_y() * settings.fluid_size,
-        );
-        let size = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-
-        // Pressure is linearly sampled in `subtract_gradient.comp.wgsl`, which
-        // requires `FLOAT32_FILTERABLE` for an R32Float texture. The shaders
-        // only touch the `.x` channel, so widening the fallback to Rgba16Float
-        // is semantically a no-op — see `downgrade_float_storage` for why we
-        // can't fall back to the narrower R16Float.
-        let pressure_format = if caps.float32_filterable {
-            wgpu::TextureFormat::R32Float
-        } else {
-            wgpu::TextureFormat::Rgba16Float
-        };
-
-        // Uniforms
-
-        let fluid_uniforms = FluidUniforms::new(&size, settings);
-        let fluid_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("uniform:FluidUniforms"),
-            contents: bytemuck::cast_slice(&[fluid_uniforms]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
         // Textures
 
         let velocity_textures = [
@@ -182,6 +191,7 @@ impl Context {
                 view_formats: &[],
                 usage: wgpu::TextureUsages::TEXTURE_BINDING
                     | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
                     | wgpu::TextureUsages::COPY_DST,
             }),
             device.create_texture(&wgpu::TextureDescriptor {
@@ -194,10 +204,24 @@ impl Context {
                 view_formats: &[],
                 usage: wgpu::TextureUsages::TEXTURE_BINDING
                     | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
                     | wgpu::TextureUsages::COPY_DST,
             }),
         ];
 
+        let previous_velocity_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture:previous_velocity"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+
         let advection_forward_texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("texture:advection_forward"),
             size,
@@ -234,6 +258,7 @@ impl Context {
             view_formats: &[],
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::COPY_DST,
         });
 
@@ -248,6 +273,7 @@ impl Context {
                 view_formats: &[],
                 usage: wgpu::TextureUsages::TEXTURE_BINDING
                     | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
                     | wgpu::TextureUsages::COPY_DST,
             }),
             device.create_texture(&wgpu::TextureDescriptor {
@@ -260,10 +286,60 @@ impl Context {
                 view_formats: &[],
                 usage: wgpu::TextureUsages::STORAGE_BINDING
                     | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
                     | wgpu::TextureUsages::COPY_DST,
             }),
         ];
 
+        // One byte per cell: 255 for solid, 0 for open. Starts out empty.
+        let obstacle_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture:obstacles"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let create_dye_texture = |label| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                view_formats: &[],
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+            })
+        };
+        let dye_textures = [
+            create_dye_texture("texture:dye_0"),
+            create_dye_texture("texture:dye_1"),
+        ];
+        let dye_advection_forward_texture = create_dye_texture("texture:dye_advection_forward");
+        let dye_advection_reverse_texture = create_dye_texture("texture:dye_advection_reverse");
+
+        let vorticity_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture:vorticity"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_DST,
+        });
+
         // Texture views
 
         let velocity_texture_views = [
@@ -277,6 +353,12 @@ impl Context {
             }),
         ];
 
+        let previous_velocity_texture_view =
+            previous_velocity_texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("view:previous_velocity"),
+                ..Default::default()
+            });
+
         let advection_forward_texture_view =
             advection_forward_texture.create_view(&wgpu::TextureViewDescriptor {
                 label: Some("view:advection_forward"),
@@ -306,61 +388,53 @@ impl Context {
             }),
         ];
 
-        // Samplers
-
-        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("sampler:linear"),
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
+        let obstacle_texture_view = obstacle_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("view:obstacles"),
             ..Default::default()
         });
 
-        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("sampler:nearest"),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
+        let dye_texture_views = [
+            dye_textures[0].create_view(&wgpu::TextureViewDescriptor {
+                label: Some("view:dye_0"),
+                ..Default::default()
+            }),
+            dye_textures[1].create_view(&wgpu::TextureViewDescriptor {
+                label: Some("view:dye_1"),
+                ..Default::default()
+            }),
+        ];
+        let dye_advection_forward_texture_view =
+            dye_advection_forward_texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("view:dye_advection_forward"),
+                ..Default::default()
+            });
+        let dye_advection_reverse_texture_view =
+            dye_advection_reverse_texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("view:dye_advection_reverse"),
+                ..Default::default()
+            });
+
+        let vorticity_texture_view = vorticity_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("view:vorticity"),
             ..Default::default()
         });
 
-        // Bind group layouts
+        // Bind groups
 
-        let velocity_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Velocity bind group layout"),
-                entries: &[
-                    // velocity_texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // out_velocity_texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: wgpu::TextureFormat::Rgba16Float,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        let uniform_bind_group = build_uniform_bind_group(
+            device,
+            "bind_group:uniform",
+            &layouts.uniform,
+            uniform_buffer,
+            linear_sampler,
+            nearest_sampler,
+            &obstacle_texture_view,
+        );
 
         let velocity_bind_groups = [
             device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("bind_group:velocity_0"),
-                layout: &velocity_bind_group_layout,
+                layout: &layouts.velocity,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
@@ -374,7 +448,7 @@ impl Context {
             }),
             device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("bind_group:velocity_1"),
-                layout: &velocity_bind_group_layout,
+                layout: &layouts.velocity,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
@@ -388,55 +462,903 @@ impl Context {
             }),
         ];
 
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("bind_group_layout:uniform"),
+        let advection_forward_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:advection_forward"),
+            layout: &layouts.advection,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&advection_forward_texture_view),
+            }],
+        });
+
+        let advection_reverse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:advection_reverse"),
+            layout: &layouts.advection,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&advection_reverse_texture_view),
+            }],
+        });
+
+        // For the reverse advection pass (MacCormack step 2), the input should be the forward-advected texture, not the original velocity.
+        let advection_reverse_input_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bind_group:advection_reverse_input"),
+                layout: &layouts.velocity,
                 entries: &[
-                    // fluid_uniforms
-                    wgpu::BindGroupLayoutEntry {
+                    wgpu::BindGroupEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
+                        resource: wgpu::BindingResource::TextureView(
+                            &advection_forward_texture_view,
+                        ),
                     },
-                    // linear_sampler
-                    wgpu::BindGroupLayoutEntry {
+                    wgpu::BindGroupEntry {
                         binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    // nearest_sampler
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
+                        resource: wgpu::BindingResource::TextureView(&velocity_texture_views[0]),
                     },
                 ],
             });
 
-        let advection_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("bind_group_layout:uniform"),
-                entries: &[
-                    // out_texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: wgpu::TextureFormat::Rgba16Float,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        let adjust_advection_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:adjust_advection"),
+            layout: &layouts.adjust_advection,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&advection_forward_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&advection_reverse_texture_view),
+                },
+            ],
+        });
+
+        let advection_velocity_bind_groups = [1, 0].map(|output| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("bind_group:advection_velocity_{}", 1 - output)),
+                layout: &layouts.advection,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&velocity_texture_views[output]),
+                }],
+            })
+        });
+
+        // BFECC reuses the forward texture for the corrected velocity, once
+        // the reverse pass is done with it.
+        let bfecc_correction_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:bfecc_correction"),
+            layout: &layouts.velocity,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&advection_reverse_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&advection_forward_texture_view),
+                },
+            ],
+        });
+
+        let bfecc_advection_bind_groups = [1, 0].map(|output| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("bind_group:bfecc_advection_{}", 1 - output)),
+                layout: &layouts.velocity,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(
+                            &advection_forward_texture_view,
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(
+                            &velocity_texture_views[output],
+                        ),
+                    },
+                ],
+            })
+        });
+
+        let divergence_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:divergence"),
+            layout: &layouts.divergence,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(nearest_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&divergence_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&obstacle_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let divergence_sample_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:divergence_sample"),
+            layout: &layouts.divergence_sample,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&divergence_texture_view),
+            }],
+        });
+
+        let pressure_bind_groups = [
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bind_group:pressure_0"),
+                layout: &layouts.pressure,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[0]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[1]),
+                    },
+                ],
+            }),
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bind_group:pressure_1"),
+                layout: &layouts.pressure,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[0]),
+                    },
+                ],
+            }),
+        ];
+
+        // The dye passes read one texture and write another, like the velocity
+        // passes do, so they share the velocity layout.
+        let create_dye_bind_group = |label, input, output| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &layouts.velocity,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(input),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(output),
+                    },
+                ],
+            })
+        };
+        let dye_advection_forward_bind_group = create_dye_bind_group(
+            "bind_group:dye_advection_forward",
+            &dye_texture_views[0],
+            &dye_advection_forward_texture_view,
+        );
+        let dye_advection_reverse_bind_group = create_dye_bind_group(
+            "bind_group:dye_advection_reverse",
+            &dye_advection_forward_texture_view,
+            &dye_advection_reverse_texture_view,
+        );
+        let dye_adjust_bind_group = create_dye_bind_group(
+            "bind_group:dye_adjust",
+            &dye_texture_views[0],
+            &dye_texture_views[1],
+        );
+        let dye_inject_bind_group = create_dye_bind_group(
+            "bind_group:dye_inject",
+            &dye_texture_views[1],
+            &dye_texture_views[0],
+        );
+
+        let dye_adjust_advection_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bind_group:dye_adjust_advection"),
+                layout: &layouts.adjust_advection,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(
+                            &dye_advection_forward_texture_view,
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(
+                            &dye_advection_reverse_texture_view,
+                        ),
+                    },
+                ],
+            });
+
+        let vorticity_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:vorticity"),
+            layout: &layouts.vorticity,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&vorticity_texture_view),
+            }],
+        });
+
+        // The curl is read with textureLoad, like the divergence.
+        let vorticity_sample_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:vorticity_sample"),
+            layout: &layouts.divergence_sample,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&vorticity_texture_view),
+            }],
+        });
+
+        let mut multigrid_levels: Vec<MultigridLevel> = Vec::new();
+        let mut level_size = size;
+        loop {
+            level_size = wgpu::Extent3d {
+                width: level_size.width.div_ceil(2),
+                height: level_size.height.div_ceil(2),
+                depth_or_array_layers: 1,
+            };
+            if level_size.width < MULTIGRID_MIN_SIZE || level_size.height < MULTIGRID_MIN_SIZE {
+                break;
+            }
+
+            let finer_pressure_texture_views = multigrid_levels
+                .last()
+                .map_or(&pressure_texture_views, |level| {
+                    &level.pressure_texture_views
+                });
+            let level = MultigridLevel::new(
+                device,
+                multigrid_levels.len() + 1,
+                level_size,
+                pressure_format,
+                layouts,
+                finer_pressure_texture_views,
+                uniform_buffer,
+                linear_sampler,
+                nearest_sampler,
+            );
+            multigrid_levels.push(level);
+        }
+
+        Self {
+            velocity_textures,
+            velocity_texture_views,
+            previous_velocity_texture,
+            previous_velocity_texture_view,
+            _advection_forward_texture: advection_forward_texture,
+            advection_forward_texture_view,
+            _advection_reverse_texture: advection_reverse_texture,
+            _advection_reverse_texture_view: advection_reverse_texture_view,
+            divergence_texture,
+            divergence_texture_view,
+            pressure_textures,
+            pressure_texture_views,
+            obstacle_texture,
+            obstacle_texture_view,
+            dye_textures,
+            dye_texture_views,
+            _dye_advection_forward_texture: dye_advection_forward_texture,
+            _dye_advection_reverse_texture: dye_advection_reverse_texture,
+            _vorticity_texture: vorticity_texture,
+            vorticity_texture_view,
+
+            uniform_bind_group,
+            velocity_bind_groups,
+            advection_forward_bind_group,
+            advection_reverse_bind_group,
+            advection_reverse_input_bind_group,
+            adjust_advection_bind_group,
+            advection_velocity_bind_groups,
+            bfecc_correction_bind_group,
+            bfecc_advection_bind_groups,
+            divergence_bind_group,
+            divergence_sample_bind_group,
+            pressure_bind_groups,
+            dye_advection_forward_bind_group,
+            dye_advection_reverse_bind_group,
+            dye_adjust_advection_bind_group,
+            dye_adjust_bind_group,
+            dye_inject_bind_group,
+            vorticity_bind_group,
+            vorticity_sample_bind_group,
+
+            multigrid_levels,
+        }
+    }
+}
+
+/// A coarser copy of the pressure problem for the multigrid solver. It solves
+/// for a correction to the next finer level's pressure, with that level's
+/// residual as its divergence.
+struct MultigridLevel {
+    size: wgpu::Extent3d,
+    _divergence_texture: wgpu::Texture,
+    _pressure_textures: [wgpu::Texture; 2],
+    pressure_texture_views: [wgpu::TextureView; 2],
+    obstacle_texture: wgpu::Texture,
+
+    uniform_bind_group: wgpu::BindGroup,
+    divergence_sample_bind_group: wgpu::BindGroup,
+    pressure_bind_groups: [wgpu::BindGroup; 2],
+    pressure_sample_bind_groups: [wgpu::BindGroup; 2],
+    // Reads the finer level's pressure, indexed by its ping-pong index, and
+    // writes this level's divergence.
+    restrict_bind_groups: [wgpu::BindGroup; 2],
+    clear_bind_group: wgpu::BindGroup,
+}
+
+impl MultigridLevel {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        device: &wgpu::Device,
+        level: usize,
+        size: wgpu::Extent3d,
+        pressure_format: wgpu::TextureFormat,
+        layouts: &Layouts,
+        finer_pressure_texture_views: &[wgpu::TextureView; 2],
+        uniform_buffer: &wgpu::Buffer,
+        linear_sampler: &wgpu::Sampler,
+        nearest_sampler: &wgpu::Sampler,
+    ) -> Self {
+        // Everything is read with textureLoad, so the pressure format works
+        // for all of it, and matches the shaders' storage format on both
+        // paths of `downgrade_float_storage`.
+        let create_texture = |name: &str, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(&format!("texture:multigrid_{}_{}", name, level)),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                view_formats: &[],
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_DST,
+            })
+        };
+        let divergence_texture = create_texture("divergence", pressure_format);
+        let pressure_textures = [
+            create_texture("pressure_0", pressure_format),
+            create_texture("pressure_1", pressure_format),
+        ];
+        let obstacle_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&format!("texture:multigrid_obstacles_{}", level)),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let divergence_texture_view = divergence_texture.create_view(&Default::default());
+        let pressure_texture_views = [
+            pressure_textures[0].create_view(&Default::default()),
+            pressure_textures[1].create_view(&Default::default()),
+        ];
+        let obstacle_texture_view = obstacle_texture.create_view(&Default::default());
+
+        let uniform_bind_group = build_uniform_bind_group(
+            device,
+            &format!("bind_group:multigrid_uniform_{}", level),
+            &layouts.uniform,
+            uniform_buffer,
+            linear_sampler,
+            nearest_sampler,
+            &obstacle_texture_view,
+        );
+
+        let sample_bind_group = |name: &str, view| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("bind_group:multigrid_{}_{}", name, level)),
+                layout: &layouts.divergence_sample,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                }],
+            })
+        };
+        let divergence_sample_bind_group =
+            sample_bind_group("divergence_sample", &divergence_texture_view);
+        let pressure_sample_bind_groups = [
+            sample_bind_group("pressure_sample_0", &pressure_texture_views[0]),
+            sample_bind_group("pressure_sample_1", &pressure_texture_views[1]),
+        ];
+
+        let pressure_bind_group = |name: &str, view, out_view| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("bind_group:multigrid_{}_{}", name, level)),
+                layout: &layouts.pressure,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(out_view),
+                    },
+                ],
+            })
+        };
+        let pressure_bind_groups = [
+            pressure_bind_group(
+                "pressure_0",
+                &pressure_texture_views[0],
+                &pressure_texture_views[1],
+            ),
+            pressure_bind_group(
+                "pressure_1",
+                &pressure_texture_views[1],
+                &pressure_texture_views[0],
+            ),
+        ];
+        let restrict_bind_groups = [
+            pressure_bind_group(
+                "restrict_0",
+                &finer_pressure_texture_views[0],
+                &divergence_texture_view,
+            ),
+            pressure_bind_group(
+                "restrict_1",
+                &finer_pressure_texture_views[1],
+                &divergence_texture_view,
+            ),
+        ];
+
+        let clear_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("bind_group:multigrid_clear_{}", level)),
+            layout: &layouts.multigrid_clear,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&pressure_texture_views[0]),
+            }],
+        });
+
+        Self {
+            size,
+            _divergence_texture: divergence_texture,
+            _pressure_textures: pressure_textures,
+            pressure_texture_views,
+            obstacle_texture,
+
+            uniform_bind_group,
+            divergence_sample_bind_group,
+            pressure_bind_groups,
+            pressure_sample_bind_groups,
+            restrict_bind_groups,
+            clear_bind_group,
+        }
+    }
+}
+
+// The bind groups the pressure passes need for one level of the multigrid
+// hierarchy. Level 0 is the fluid's own pressure field.
+struct PressureGrid<'a> {
+    size: wgpu::Extent3d,
+    uniform_bind_group: &'a wgpu::BindGroup,
+    divergence_sample_bind_group: &'a wgpu::BindGroup,
+    pressure_bind_groups: &'a [wgpu::BindGroup; 2],
+}
+
+fn build_uniform_bind_group(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    linear_sampler: &wgpu::Sampler,
+    nearest_sampler: &wgpu::Sampler,
+    obstacle_texture_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(linear_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(nearest_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(obstacle_texture_view),
+            },
+        ],
+    })
+}
+
+fn create_samplers(
+    device: &wgpu::Device,
+    boundary: settings::Boundary,
+) -> (wgpu::Sampler, wgpu::Sampler) {
+    let address_mode = address_mode(boundary);
+
+    let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("sampler:linear"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        ..Default::default()
+    });
+
+    let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("sampler:nearest"),
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        ..Default::default()
+    });
+
+    (linear_sampler, nearest_sampler)
+}
+
+fn dispatch_over(cpass: &mut wgpu::ComputePass, size: wgpu::Extent3d) {
+    cpass.dispatch_workgroups(size.width.div_ceil(16), size.height.div_ceil(16), 1);
+}
+
+fn write_obstacles(queue: &wgpu::Queue, texture: &wgpu::Texture, cells: &[u8]) {
+    let size = texture.size();
+    debug_assert_eq!(cells.len(), (size.width * size.height) as usize);
+    queue.write_texture(
+        texture.as_image_copy(),
+        cells,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(size.width),
+            rows_per_image: None,
+        },
+        size,
+    );
+}
+
+// The root-mean-square residual of the discrete pressure equation solved by
+// `solve_pressure.comp.wgsl` over the open cells, relative to that of a zero
+// pressure field. With a periodic boundary, the edges wrap around.
+fn relative_residual(
+    pressure: &[f32],
+    divergence: &[f32],
+    solid: &[u8],
+    width: usize,
+    height: usize,
+    periodic: bool,
+) -> f32 {
+    let mut residual_sum = 0.0;
+    let mut divergence_sum = 0.0;
+
+    for y in 0..height {
+        for x in 0..width {
+            // Nothing reads the pressure inside obstacles.
+            let index = y * width + x;
+            if solid[index] != 0 {
+                continue;
+            }
+
+            let center = pressure[index];
+            let neighbour = |dx: isize, dy: isize| {
+                let (mut nx, mut ny) = (x as isize + dx, y as isize + dy);
+                if periodic {
+                    nx = nx.rem_euclid(width as isize);
+                    ny = ny.rem_euclid(height as isize);
+                }
+                if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                    return center;
+                }
+
+                let index = ny as usize * width + nx as usize;
+                if solid[index] != 0 {
+                    center
+                } else {
+                    pressure[index]
+                }
+            };
+            let sum = neighbour(-1, 0) + neighbour(1, 0) + neighbour(0, -1) + neighbour(0, 1);
+
+            let residual = divergence[index] - (sum - 4.0 * center);
+            residual_sum += f64::from(residual * residual);
+            divergence_sum += f64::from(divergence[index] * divergence[index]);
+        }
+    }
+
+    if divergence_sum == 0.0 {
+        return 0.0;
+    }
+    (residual_sum / divergence_sum).sqrt() as f32
+}
+
+pub struct Context {
+    fluid_size: [f32; 2],
+    fluid_size_3d: wgpu::Extent3d,
+    pressure_format: wgpu::TextureFormat,
+
+    diffusion_iterations: u32,
+    pressure_mode: settings::PressureMode,
+    pressure_iterations: u32,
+    pressure_solver: settings::PressureSolver,
+    boundary: settings::Boundary,
+    dye_enabled: bool,
+    vorticity_enabled: bool,
+    substeps: u32,
+
+    fluid_uniforms: FluidUniforms,
+    // The dye step shares the fluid timestep, so it's kept here to be
+    // rewritten along with the fluid uniforms.
+    dye_uniforms: DyeUniforms,
+    fluid_uniform_buffer: wgpu::Buffer,
+    dye_uniform_buffer: wgpu::Buffer,
+    dye_uniform_bind_group: wgpu::BindGroup,
+
+    linear_sampler: wgpu::Sampler,
+    nearest_sampler: wgpu::Sampler,
+    layouts: Layouts,
+    fields: Fields,
+
+    advection_forward_direction_bind_group: wgpu::BindGroup,
+    advection_reverse_direction_bind_group: wgpu::BindGroup,
+
+    advection_pipeline: wgpu::ComputePipeline,
+    adjust_advection_pipeline: wgpu::ComputePipeline,
+    correct_bfecc_pipeline: wgpu::ComputePipeline,
+    advect_bfecc_pipeline: wgpu::ComputePipeline,
+    diffusion_pipeline: wgpu::ComputePipeline,
+    divergence_pipeline: wgpu::ComputePipeline,
+    pressure_pipeline: wgpu::ComputePipeline,
+    smooth_pressure_pipeline: wgpu::ComputePipeline,
+    restrict_pressure_pipeline: wgpu::ComputePipeline,
+    prolongate_pressure_pipeline: wgpu::ComputePipeline,
+    center_pressure_pipeline: wgpu::ComputePipeline,
+    subtract_gradient_pipeline: wgpu::ComputePipeline,
+    resample_velocity_pipeline: wgpu::ComputePipeline,
+    advect_dye_pipeline: wgpu::ComputePipeline,
+    adjust_dye_advection_pipeline: wgpu::ComputePipeline,
+    inject_dye_pipeline: wgpu::ComputePipeline,
+    vorticity_pipeline: wgpu::ComputePipeline,
+    confine_vorticity_pipeline: wgpu::ComputePipeline,
+
+    last_pressure_index: Arc<Mutex<usize>>,
+    last_velocity_index: Arc<Mutex<usize>>,
+
+    speed_probe: speed::Probe,
+}
+
+impl Context {
+    /// Applies new settings. Returns whether the fluid textures were
+    /// reallocated, which replaces every texture view handed out before.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scaling_ratio: grid::ScalingRatio,
+        settings: &Arc<Settings>,
+    ) -> bool {
+        let (width, height) = (
+            scaling_ratio.rounded_x() * settings.fluid_size,
+            scaling_ratio.rounded_y() * settings.fluid_size,
+        );
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        // The samplers wrap around the edges of a periodic domain. They're
+        // baked into the bind groups, so swapping them means rebuilding the
+        // fields, even at the same size.
+        let rewrap = (self.boundary == settings::Boundary::Periodic)
+            != (settings.boundary == settings::Boundary::Periodic);
+        if rewrap {
+            (self.linear_sampler, self.nearest_sampler) =
+                create_samplers(device, settings.boundary);
+        }
+
+        // Resize the fluid texture if necessary
+        let reallocate = rewrap || self.fluid_size_3d != size;
+        if reallocate {
+            self.resize(device, queue, size);
+        }
+
+        // Update fluid settings needed on the CPU side
+        self.diffusion_iterations = settings.diffusion_iterations;
+        self.pressure_mode = settings.pressure_mode;
+        self.pressure_iterations = settings.pressure_iterations;
+        self.pressure_solver = settings.pressure_solver;
+        self.boundary = settings.boundary;
+        self.dye_enabled = settings.uses_dye();
+        self.vorticity_enabled = settings.uses_vorticity();
+
+        // Update uniforms
+        self.write_uniforms(queue, settings);
+
+        reallocate
+    }
+
+    /// Sets how many times the caller runs the fluid passes per fluid step.
+    /// The uniforms are shared by every pass in a submission, so the count
+    /// applies to all the steps encoded before the next submit.
+    pub fn set_substeps(&mut self, queue: &wgpu::Queue, settings: &Settings, substeps: u32) {
+        if self.substeps != substeps {
+            self.substeps = substeps;
+            self.write_uniforms(queue, settings);
+        }
+    }
+
+    fn write_uniforms(&mut self, queue: &wgpu::Queue, settings: &Settings) {
+        self.fluid_uniforms = FluidUniforms::new(&self.fluid_size_3d, settings, self.substeps);
+        queue.write_buffer(
+            &self.fluid_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.fluid_uniforms]),
+        );
+
+        self.dye_uniforms.timestep = self.fluid_uniforms.timestep;
+        queue.write_buffer(
+            &self.dye_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.dye_uniforms),
+        );
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        scaling_ratio: grid::ScalingRatio,
+        caps: BackendCaps,
+        settings: &Arc<Settings>,
+    ) -> Self {
+        let (width, height) = (
+            scaling_ratio.rounded_x() * settings.fluid_size,
+            scaling_ratio.rounded_y() * settings.fluid_size,
+        );
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        // Pressure is linearly sampled in `subtract_gradient.comp.wgsl`, which
+        // requires `FLOAT32_FILTERABLE` for an R32Float texture. The shaders
+        // only touch the `.x` channel, so widening the fallback to Rgba16Float
+        // is semantically a no-op — see `downgrade_float_storage` for why we
+        // can't fall back to the narrower R16Float.
+        let pressure_format = if caps.float32_filterable {
+            wgpu::TextureFormat::R32Float
+        } else {
+            wgpu::TextureFormat::Rgba16Float
+        };
+
+        // Uniforms
+
+        let fluid_uniforms = FluidUniforms::new(&size, settings, 1);
+        let fluid_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("uniform:FluidUniforms"),
+            contents: bytemuck::cast_slice(&[fluid_uniforms]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Samplers
+
+        let (linear_sampler, nearest_sampler) = create_samplers(device, settings.boundary);
+
+        // Bind group layouts
+
+        let velocity_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Velocity bind group layout"),
+                entries: &[
+                    // velocity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // out_velocity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba16Float,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:uniform"),
+                entries: &[
+                    // fluid_uniforms
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // linear_sampler
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    // nearest_sampler
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    // obstacle_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let advection_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:uniform"),
+                entries: &[
+                    // out_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba16Float,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
 
         let advection_direction_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -453,29 +1375,6 @@ impl Context {
                 }],
             });
 
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind group:uniform"),
-            layout: &uniform_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &fluid_uniform_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&linear_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&nearest_sampler),
-                },
-            ],
-        });
-
         let forward_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("uniform:forward"),
             contents: bytemuck::cast_slice(&[Direction {
@@ -521,43 +1420,6 @@ impl Context {
                 }],
             });
 
-        let advection_forward_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:advection_forward"),
-            layout: &advection_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&advection_forward_texture_view),
-            }],
-        });
-
-        let advection_reverse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:advection_reverse"),
-            layout: &advection_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&advection_reverse_texture_view),
-            }],
-        });
-
-        // For the reverse advection pass (MacCormack step 2), the input should be the forward-advected texture, not the original velocity.
-        let advection_reverse_input_bind_group =
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("bind_group:advection_reverse_input"),
-                layout: &velocity_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(
-                            &advection_forward_texture_view,
-                        ),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::TextureView(&velocity_texture_views[0]),
-                    },
-                ],
-            });
-
         let advection_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Advection layout"),
@@ -572,9 +1434,10 @@ impl Context {
 
         let advection_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:advection"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/advect.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/advect.comp.wgsl"
+            )))),
         });
 
         let advection_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
@@ -618,21 +1481,6 @@ impl Context {
                 ],
             });
 
-        let adjust_advection_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:adjust_advection"),
-            layout: &adjust_advection_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&advection_forward_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&advection_reverse_texture_view),
-                },
-            ],
-        });
-
         let adjust_advection_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("pipeline_layout:adjust_advection"),
@@ -646,9 +1494,10 @@ impl Context {
 
         let adjust_advection_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:adjust_advection"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/adjust_advection.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/adjust_advection.comp.wgsl"
+            )))),
         });
 
         let adjust_advection_pipeline =
@@ -661,11 +1510,59 @@ impl Context {
                 cache: None,
             });
 
+        let bfecc_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:bfecc"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let correct_bfecc_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:correct_bfecc"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/correct_bfecc.comp.wgsl"
+            )))),
+        });
+
+        let correct_bfecc_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:correct_bfecc"),
+                layout: Some(&bfecc_pipeline_layout),
+                module: &correct_bfecc_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        let advect_bfecc_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:advect_bfecc"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/advect_bfecc.comp.wgsl"
+            )))),
+        });
+
+        let advect_bfecc_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:advect_bfecc"),
+                layout: Some(&bfecc_pipeline_layout),
+                module: &advect_bfecc_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
         let diffusion_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:diffusion"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/diffuse.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/diffuse.comp.wgsl"
+            )))),
         });
 
         let diffusion_pipeline_layout =
@@ -709,23 +1606,30 @@ impl Context {
                         },
                         count: None,
                     },
+                    // obstacle_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // fluid_uniforms
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
-            });
-
-        let divergence_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:divergence"),
-            layout: &divergence_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Sampler(&nearest_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&divergence_texture_view),
-                },
-            ],
-        });
+            });
 
         let divergence_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -739,9 +1643,10 @@ impl Context {
 
         let divergence_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:divergence"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/divergence.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/divergence.comp.wgsl"
+            )))),
         });
 
         let divergence_pipeline =
@@ -775,15 +1680,6 @@ impl Context {
                 ],
             });
 
-        let divergence_sample_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:divergence_sample"),
-            layout: &divergence_sample_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&divergence_texture_view),
-            }],
-        });
-
         let pressure_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("bind_group_layout:pressure"),
@@ -813,41 +1709,10 @@ impl Context {
                 ],
             });
 
-        let pressure_bind_groups = [
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("bind_group:pressure_0"),
-                layout: &pressure_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[0]),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[1]),
-                    },
-                ],
-            }),
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("bind_group:pressure_1"),
-                layout: &pressure_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[1]),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::TextureView(&pressure_texture_views[0]),
-                    },
-                ],
-            }),
-        ];
-
         let pressure_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:pressure"),
             source: wgpu::ShaderSource::Wgsl(downgrade_float_storage(
-                include_str!("../../shader/solve_pressure.comp.wgsl"),
+                include_str!(concat!(env!("OUT_DIR"), "/shader/solve_pressure.comp.wgsl")),
                 caps,
             )),
         });
@@ -872,11 +1737,141 @@ impl Context {
             cache: None,
         });
 
+        // Multigrid
+
+        let smooth_pressure_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:smooth_pressure"),
+                layout: Some(&pressure_pipeline_layout),
+                module: &pressure_shader,
+                entry_point: Some("main"),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &[("jacobi_weight", MULTIGRID_JACOBI_WEIGHT)],
+                    ..Default::default()
+                },
+                cache: None,
+            });
+
+        let multigrid_clear_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:multigrid_clear"),
+                entries: &[
+                    // out_coarse_pressure_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: pressure_format,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let restrict_pressure_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:restrict_pressure"),
+            source: wgpu::ShaderSource::Wgsl(downgrade_float_storage(
+                include_str!(concat!(
+                    env!("OUT_DIR"),
+                    "/shader/restrict_pressure.comp.wgsl"
+                )),
+                caps,
+            )),
+        });
+
+        let restrict_pressure_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:restrict_pressure"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&divergence_sample_bind_group_layout),
+                    Some(&pressure_bind_group_layout),
+                    Some(&multigrid_clear_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let restrict_pressure_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:restrict_pressure"),
+                layout: Some(&restrict_pressure_pipeline_layout),
+                module: &restrict_pressure_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        let prolongate_pressure_shader =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("shader:prolongate_pressure"),
+                source: wgpu::ShaderSource::Wgsl(downgrade_float_storage(
+                    include_str!(concat!(
+                        env!("OUT_DIR"),
+                        "/shader/prolongate_pressure.comp.wgsl"
+                    )),
+                    caps,
+                )),
+            });
+
+        let prolongate_pressure_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:prolongate_pressure"),
+                bind_group_layouts: &[
+                    Some(&divergence_sample_bind_group_layout),
+                    Some(&pressure_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let prolongate_pressure_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:prolongate_pressure"),
+                layout: Some(&prolongate_pressure_pipeline_layout),
+                module: &prolongate_pressure_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        let center_pressure_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:center_pressure"),
+            source: wgpu::ShaderSource::Wgsl(downgrade_float_storage(
+                include_str!(concat!(
+                    env!("OUT_DIR"),
+                    "/shader/center_pressure.comp.wgsl"
+                )),
+                caps,
+            )),
+        });
+
+        let center_pressure_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:center_pressure"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&pressure_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let center_pressure_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:center_pressure"),
+                layout: Some(&center_pressure_pipeline_layout),
+                module: &center_pressure_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
         let subtract_gradient_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:subtract_gradient"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/subtract_gradient.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/subtract_gradient.comp.wgsl"
+            )))),
         });
 
         let subtract_gradient_pipeline_layout =
@@ -892,58 +1887,407 @@ impl Context {
 
         let subtract_gradient_pipeline =
             device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("pipeline:subtract_gradient"),
-                layout: Some(&subtract_gradient_pipeline_layout),
-                module: &subtract_gradient_shader,
+                label: Some("pipeline:subtract_gradient"),
+                layout: Some(&subtract_gradient_pipeline_layout),
+                module: &subtract_gradient_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        let resample_velocity_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:resample_velocity"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/resample_velocity.comp.wgsl"
+            )))),
+        });
+
+        let resample_velocity_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:resample_velocity"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let resample_velocity_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:resample_velocity"),
+                layout: Some(&resample_velocity_pipeline_layout),
+                module: &resample_velocity_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        // Dye
+
+        let dye_uniforms = DyeUniforms {
+            timestep: fluid_uniforms.timestep,
+            ..DyeUniforms::zeroed()
+        };
+        let dye_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("uniform:dye"),
+            contents: bytemuck::bytes_of(&dye_uniforms),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let dye_uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:dye_uniforms"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let dye_uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:dye_uniforms"),
+            layout: &dye_uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: dye_uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let advect_dye_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:advect_dye"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/advect_dye.comp.wgsl"
+            )))),
+        });
+
+        let advect_dye_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:advect_dye"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                    Some(&advection_direction_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let advect_dye_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:advect_dye"),
+                layout: Some(&advect_dye_pipeline_layout),
+                module: &advect_dye_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        let adjust_dye_advection_shader =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("shader:adjust_dye_advection"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                    env!("OUT_DIR"),
+                    "/shader/adjust_dye_advection.comp.wgsl"
+                )))),
+            });
+
+        let adjust_dye_advection_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:adjust_dye_advection"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&adjust_advection_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let adjust_dye_advection_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:adjust_dye_advection"),
+                layout: Some(&adjust_dye_advection_pipeline_layout),
+                module: &adjust_dye_advection_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        let inject_dye_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:inject_dye"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/inject_dye.comp.wgsl"
+            )))),
+        });
+
+        let inject_dye_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:inject_dye"),
+                bind_group_layouts: &[
+                    Some(&dye_uniform_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let inject_dye_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:inject_dye"),
+                layout: Some(&inject_dye_pipeline_layout),
+                module: &inject_dye_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        // Vorticity
+
+        let vorticity_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:vorticity"),
+                entries: &[
+                    // out_vorticity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::R32Float,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let vorticity_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:vorticity"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/calculate_vorticity.comp.wgsl"
+            )))),
+        });
+
+        let vorticity_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:vorticity"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&vorticity_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let vorticity_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("pipeline:vorticity"),
+            layout: Some(&vorticity_pipeline_layout),
+            module: &vorticity_shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let confine_vorticity_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:confine_vorticity"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/confine_vorticity.comp.wgsl"
+            )))),
+        });
+
+        let confine_vorticity_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:confine_vorticity"),
+                bind_group_layouts: &[
+                    Some(&uniform_bind_group_layout),
+                    Some(&divergence_sample_bind_group_layout),
+                    Some(&velocity_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let confine_vorticity_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:confine_vorticity"),
+                layout: Some(&confine_vorticity_pipeline_layout),
+                module: &confine_vorticity_shader,
                 entry_point: Some("main"),
                 compilation_options: Default::default(),
                 cache: None,
             });
 
+        let layouts = Layouts {
+            uniform: uniform_bind_group_layout,
+            velocity: velocity_bind_group_layout,
+            advection: advection_bind_group_layout,
+            adjust_advection: adjust_advection_bind_group_layout,
+            divergence: divergence_bind_group_layout,
+            divergence_sample: divergence_sample_bind_group_layout,
+            pressure: pressure_bind_group_layout,
+            vorticity: vorticity_bind_group_layout,
+            multigrid_clear: multigrid_clear_bind_group_layout,
+        };
+
+        let fields = Fields::new(
+            device,
+            size,
+            pressure_format,
+            &layouts,
+            &fluid_uniform_buffer,
+            &linear_sampler,
+            &nearest_sampler,
+        );
+
         Self {
             fluid_size: [width as f32, height as f32],
             fluid_size_3d: size,
+            pressure_format,
 
             diffusion_iterations: settings.diffusion_iterations,
             pressure_mode: settings.pressure_mode,
             pressure_iterations: settings.pressure_iterations,
+            pressure_solver: settings.pressure_solver,
+            boundary: settings.boundary,
+            dye_enabled: settings.uses_dye(),
+            vorticity_enabled: settings.uses_vorticity(),
+            substeps: 1,
 
             fluid_uniforms,
+            dye_uniforms,
             fluid_uniform_buffer,
+            dye_uniform_buffer,
+            dye_uniform_bind_group,
 
-            _velocity_textures: velocity_textures,
-            velocity_texture_views,
-            _advection_forward_texture: advection_forward_texture,
-            advection_forward_texture_view,
-            _advection_reverse_texture: advection_reverse_texture,
-            _advection_reverse_texture_view: advection_reverse_texture_view,
-            _divergence_texture: divergence_texture,
-            divergence_texture_view,
-            pressure_textures,
-            pressure_texture_views,
+            linear_sampler,
+            nearest_sampler,
+            layouts,
+            fields,
 
-            velocity_bind_groups,
-            uniform_bind_group,
-            advection_forward_bind_group,
-            advection_reverse_bind_group,
             advection_forward_direction_bind_group,
             advection_reverse_direction_bind_group,
-            advection_reverse_input_bind_group,
-            adjust_advection_bind_group,
-            divergence_bind_group,
-            divergence_sample_bind_group,
-            pressure_bind_groups,
 
             advection_pipeline,
             adjust_advection_pipeline,
+            correct_bfecc_pipeline,
+            advect_bfecc_pipeline,
             diffusion_pipeline,
             divergence_pipeline,
             pressure_pipeline,
+            smooth_pressure_pipeline,
+            restrict_pressure_pipeline,
+            prolongate_pressure_pipeline,
+            center_pressure_pipeline,
             subtract_gradient_pipeline,
+            resample_velocity_pipeline,
+            advect_dye_pipeline,
+            adjust_dye_advection_pipeline,
+            inject_dye_pipeline,
+            vorticity_pipeline,
+            confine_vorticity_pipeline,
 
             last_pressure_index: Arc::new(Mutex::new(0)),
             last_velocity_index: Arc::new(Mutex::new(0)),
+
+            speed_probe: speed::Probe::new(device),
+        }
+    }
+
+    /// Reallocate the fluid textures at a new size.
+    ///
+    /// The current velocity field and dye are resampled into the new
+    /// textures, so the flow carries over. Pressure restarts from zero; the
+    /// solver recovers it within a few frames. The obstacle mask is cleared
+    /// and needs to be set again at the new size.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: wgpu::Extent3d) {
+        let fields = Fields::new(
+            device,
+            size,
+            self.pressure_format,
+            &self.layouts,
+            &self.fluid_uniform_buffer,
+            &self.linear_sampler,
+            &self.nearest_sampler,
+        );
+
+        let mut velocity_index = self.last_velocity_index.lock().unwrap();
+
+        let resample_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:resample_velocity"),
+            layout: &self.layouts.velocity,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &self.fields.velocity_texture_views[*velocity_index],
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&fields.velocity_texture_views[0]),
+                },
+            ],
+        });
+
+        let resample_dye_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:resample_dye"),
+            layout: &self.layouts.velocity,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.fields.dye_texture_views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&fields.dye_texture_views[0]),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("encoder:resample_velocity"),
+        });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("compute:resample_velocity"),
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.resample_velocity_pipeline);
+            cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+            cpass.set_bind_group(1, &resample_bind_group, &[]);
+            cpass.dispatch_workgroups(size.width.div_ceil(16), size.height.div_ceil(16), 1);
+            cpass.set_bind_group(1, &resample_dye_bind_group, &[]);
+            cpass.dispatch_workgroups(size.width.div_ceil(16), size.height.div_ceil(16), 1);
         }
+        // Start both halves of the ping-pong pair, and the previous step,
+        // from the same field.
+        encoder.copy_texture_to_texture(
+            fields.velocity_textures[0].as_image_copy(),
+            fields.velocity_textures[1].as_image_copy(),
+            size,
+        );
+        encoder.copy_texture_to_texture(
+            fields.velocity_textures[0].as_image_copy(),
+            fields.previous_velocity_texture.as_image_copy(),
+            size,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        *velocity_index = 0;
+        drop(velocity_index);
+        *self.last_pressure_index.lock().unwrap() = 0;
+
+        self.fields = fields;
+        self.fluid_size = [size.width as f32, size.height as f32];
+        self.fluid_size_3d = size;
     }
 
     fn get_workgroup_size(&self) -> (u32, u32, u32) {
@@ -963,10 +2307,10 @@ impl Context {
         let velocity_index = self.last_velocity_index.lock().unwrap();
         let workgroup = self.get_workgroup_size();
         cpass.set_pipeline(&self.advection_pipeline);
-        cpass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        cpass.set_bind_group(1, &self.advection_forward_bind_group, &[]);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.advection_forward_bind_group, &[]);
         cpass.set_bind_group(2, &self.advection_forward_direction_bind_group, &[]);
-        cpass.set_bind_group(3, &self.velocity_bind_groups[*velocity_index], &[]);
+        cpass.set_bind_group(3, &self.fields.velocity_bind_groups[*velocity_index], &[]);
         cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
     }
 
@@ -977,11 +2321,11 @@ impl Context {
     ) {
         let workgroup = self.get_workgroup_size();
         cpass.set_pipeline(&self.advection_pipeline);
-        cpass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        cpass.set_bind_group(1, &self.advection_reverse_bind_group, &[]);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.advection_reverse_bind_group, &[]);
         cpass.set_bind_group(2, &self.advection_reverse_direction_bind_group, &[]);
         // MacCormack step 2: re-advect the forward-advected result (not the original velocity)
-        cpass.set_bind_group(3, &self.advection_reverse_input_bind_group, &[]);
+        cpass.set_bind_group(3, &self.fields.advection_reverse_input_bind_group, &[]);
         cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
     }
 
@@ -989,9 +2333,53 @@ impl Context {
         let mut velocity_index = self.last_velocity_index.lock().unwrap();
         let workgroup = self.get_workgroup_size();
         cpass.set_pipeline(&self.adjust_advection_pipeline);
-        cpass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        cpass.set_bind_group(1, &self.adjust_advection_bind_group, &[]);
-        cpass.set_bind_group(2, &self.velocity_bind_groups[*velocity_index], &[]);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.adjust_advection_bind_group, &[]);
+        cpass.set_bind_group(2, &self.fields.velocity_bind_groups[*velocity_index], &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+
+        *velocity_index = 1 - *velocity_index;
+    }
+
+    /// Advects the velocity with a single semi-Lagrangian trace. Replaces
+    /// `advect_forward`, `advect_reverse` and `adjust_advection`.
+    pub fn advect_semi_lagrangian<'cpass>(&'cpass self, cpass: &mut wgpu::ComputePass<'cpass>) {
+        let mut velocity_index = self.last_velocity_index.lock().unwrap();
+        let workgroup = self.get_workgroup_size();
+        cpass.set_pipeline(&self.advection_pipeline);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(
+            1,
+            &self.fields.advection_velocity_bind_groups[*velocity_index],
+            &[],
+        );
+        cpass.set_bind_group(2, &self.advection_forward_direction_bind_group, &[]);
+        cpass.set_bind_group(3, &self.fields.velocity_bind_groups[*velocity_index], &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+
+        *velocity_index = 1 - *velocity_index;
+    }
+
+    /// Finishes a BFECC step, after `advect_forward` and `advect_reverse`:
+    /// corrects the velocity by the error of the round trip and advects it
+    /// once more. Used instead of `adjust_advection`.
+    pub fn adjust_bfecc_advection<'cpass>(&'cpass self, cpass: &mut wgpu::ComputePass<'cpass>) {
+        let mut velocity_index = self.last_velocity_index.lock().unwrap();
+        let velocity_bind_group = &self.fields.velocity_bind_groups[*velocity_index];
+        let workgroup = self.get_workgroup_size();
+
+        cpass.set_pipeline(&self.correct_bfecc_pipeline);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.bfecc_correction_bind_group, &[]);
+        cpass.set_bind_group(2, velocity_bind_group, &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+
+        cpass.set_pipeline(&self.advect_bfecc_pipeline);
+        cpass.set_bind_group(
+            1,
+            &self.fields.bfecc_advection_bind_groups[*velocity_index],
+            &[],
+        );
         cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
 
         *velocity_index = 1 - *velocity_index;
@@ -1001,10 +2389,10 @@ impl Context {
         let mut velocity_index = self.last_velocity_index.lock().unwrap();
         let workgroup = self.get_workgroup_size();
         cpass.set_pipeline(&self.diffusion_pipeline);
-        cpass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
 
         for _ in 0..self.diffusion_iterations {
-            cpass.set_bind_group(1, &self.velocity_bind_groups[*velocity_index], &[]);
+            cpass.set_bind_group(1, &self.fields.velocity_bind_groups[*velocity_index], &[]);
             cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
             *velocity_index = 1 - *velocity_index;
         }
@@ -1014,35 +2402,137 @@ impl Context {
         let velocity_index = self.last_velocity_index.lock().unwrap();
         let workgroup = self.get_workgroup_size();
         cpass.set_pipeline(&self.divergence_pipeline);
-        cpass.set_bind_group(0, &self.divergence_bind_group, &[]);
-        cpass.set_bind_group(1, &self.velocity_bind_groups[*velocity_index], &[]);
+        cpass.set_bind_group(0, &self.fields.divergence_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.velocity_bind_groups[*velocity_index], &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+    }
+
+    /// Computes the curl of the velocity field. Does nothing unless the
+    /// settings use it; see `Settings::uses_vorticity`.
+    pub fn calculate_vorticity<'cpass>(&'cpass self, cpass: &mut wgpu::ComputePass<'cpass>) {
+        if !self.vorticity_enabled {
+            return;
+        }
+
+        let velocity_index = self.last_velocity_index.lock().unwrap();
+        let workgroup = self.get_workgroup_size();
+        cpass.set_pipeline(&self.vorticity_pipeline);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.vorticity_bind_group, &[]);
+        cpass.set_bind_group(2, &self.fields.velocity_bind_groups[*velocity_index], &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+    }
+
+    /// Adds the vorticity confinement force, using the curl from
+    /// `calculate_vorticity`.
+    pub fn confine_vorticity<'cpass>(&'cpass self, cpass: &mut wgpu::ComputePass<'cpass>) {
+        if self.fluid_uniforms.vorticity <= 0.0 {
+            return;
+        }
+
+        let mut velocity_index = self.last_velocity_index.lock().unwrap();
+        let workgroup = self.get_workgroup_size();
+        cpass.set_pipeline(&self.confine_vorticity_pipeline);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.vorticity_sample_bind_group, &[]);
+        cpass.set_bind_group(2, &self.fields.velocity_bind_groups[*velocity_index], &[]);
         cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+        *velocity_index = 1 - *velocity_index;
     }
 
     pub fn clear_pressure(&self, queue: &wgpu::Queue, pressure: f32) {
         let (width, height) = (self.fluid_size[0] as u32, self.fluid_size[1] as u32);
         let pixel_count = (width * height) as usize;
+        self.write_pressure(queue, &vec![pressure; pixel_count]);
+    }
+
+    /// Zeroes the velocity, pressure and dye, as after a blow-up. The
+    /// previous step's velocity is zeroed too, so there's nothing left to
+    /// interpolate from.
+    ///
+    /// The latest speed measurement, and any still being read back, is
+    /// discarded with the old field.
+    pub fn clear(&mut self, queue: &wgpu::Queue) {
+        let size = self.fluid_size_3d;
+        let texels = vec![[half::f16::ZERO; 4]; (size.width * size.height) as usize];
+        self.write_velocity(queue, &texels);
+        self.clear_pressure(queue, 0.0);
+        for dye_texture in self.fields.dye_textures.iter() {
+            queue.write_texture(
+                dye_texture.as_image_copy(),
+                bytemuck::cast_slice(&texels),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(8 * size.width),
+                    rows_per_image: None,
+                },
+                size,
+            );
+        }
+
+        self.speed_probe.discard();
+    }
+
+    // Both halves of the ping-pong pair get the same field, so it doesn't
+    // matter which one the next pass reads from. The previous step gets it
+    // too, so there's nothing to interpolate from.
+    fn write_velocity(&self, queue: &wgpu::Queue, texels: &[[half::f16; 4]]) {
+        let fields = &self.fields;
+        for velocity_texture in fields
+            .velocity_textures
+            .iter()
+            .chain([&fields.previous_velocity_texture])
+        {
+            self.write_velocity_texture(queue, velocity_texture, texels);
+        }
+    }
+
+    fn write_velocity_texture(
+        &self,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        texels: &[[half::f16; 4]],
+    ) {
+        let size = self.fluid_size_3d;
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(8 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+
+    // Upload `pressure` (one value per texel) to both pressure textures.
+    fn write_pressure(&self, queue: &wgpu::Queue, pressure: &[f32]) {
+        let (width, height) = (self.fluid_size[0] as u32, self.fluid_size[1] as u32);
 
         // The pressure texture format depends on FLOAT32_FILTERABLE support:
         // R32Float on the fast path, Rgba16Float on the fallback. Build the
         // upload buffer from whichever the actual texture is using.
-        let format = self.pressure_textures[0].format();
+        let format = self.fields.pressure_textures[0].format();
         let bytes_per_pixel = format
             .block_copy_size(None)
             .expect("pressure format is uncompressed and color-only");
         let buf: Vec<u8> = match format {
-            wgpu::TextureFormat::R32Float => {
-                bytemuck::cast_slice(&vec![pressure; pixel_count]).to_vec()
-            }
+            wgpu::TextureFormat::R32Float => bytemuck::cast_slice(pressure).to_vec(),
             wgpu::TextureFormat::Rgba16Float => {
-                let p = half::f16::from_f32(pressure);
-                let texel = [p, half::f16::ZERO, half::f16::ZERO, half::f16::ZERO];
-                bytemuck::cast_slice(&vec![texel; pixel_count]).to_vec()
+                let texels = pressure
+                    .iter()
+                    .map(|&p| {
+                        let p = half::f16::from_f32(p);
+                        [p, half::f16::ZERO, half::f16::ZERO, half::f16::ZERO]
+                    })
+                    .collect::<Vec<_>>();
+                bytemuck::cast_slice(&texels).to_vec()
             }
             other => panic!("unexpected pressure format: {other:?}"),
         };
 
-        for pressure_texture in self.pressure_textures.iter() {
+        for pressure_texture in self.fields.pressure_textures.iter() {
             queue.write_texture(
                 wgpu::TexelCopyTextureInfo {
                     texture: pressure_texture,
@@ -1061,6 +2551,170 @@ impl Context {
         }
     }
 
+    /// Reads the fluid fields and speed measurements back from the GPU.
+    /// Blocks until the copy completes. Native targets only.
+    pub fn snapshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<snapshot::FluidState> {
+        let velocity_texture =
+            &self.fields.velocity_textures[*self.last_velocity_index.lock().unwrap()];
+        let velocity = self.read_velocity(device, queue, velocity_texture)?;
+        let previous_velocity =
+            self.read_velocity(device, queue, &self.fields.previous_velocity_texture)?;
+
+        // The dye always ends a step in the first texture.
+        let dye = self
+            .read_texture(device, queue, &self.fields.dye_textures[0])?
+            .chunks_exact(2)
+            .map(|half| half::f16::from_le_bytes([half[0], half[1]]).to_f32())
+            .collect();
+
+        Ok(snapshot::FluidState {
+            width: self.fluid_size_3d.width,
+            height: self.fluid_size_3d.height,
+            velocity,
+            pressure: self.read_pressure(device, queue)?,
+            dye,
+            previous_velocity,
+            speed: self.speed_probe.snapshot(device, queue)?,
+        })
+    }
+
+    // Velocity is Rgba16Float; only .xy is meaningful.
+    fn read_velocity(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) -> Result<Vec<f32>> {
+        Ok(self
+            .read_texture(device, queue, texture)?
+            .chunks_exact(8)
+            .flat_map(|texel| {
+                [
+                    half::f16::from_le_bytes([texel[0], texel[1]]).to_f32(),
+                    half::f16::from_le_bytes([texel[2], texel[3]]).to_f32(),
+                ]
+            })
+            .collect())
+    }
+
+    /// How well the last pressure solve converged: the root-mean-square
+    /// residual, relative to that of a zero pressure field. Blocks until the
+    /// GPU has finished reading back. Native targets only.
+    pub fn relative_pressure_residual(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<f32> {
+        let pressure = self.read_pressure(device, queue)?;
+        let divergence = self
+            .read_texture(device, queue, &self.fields.divergence_texture)?
+            .chunks_exact(4)
+            .map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+            .collect::<Vec<_>>();
+        let solid = self.read_texture(device, queue, &self.fields.obstacle_texture)?;
+
+        Ok(relative_residual(
+            &pressure,
+            &divergence,
+            &solid,
+            self.fluid_size_3d.width as usize,
+            self.fluid_size_3d.height as usize,
+            self.boundary == settings::Boundary::Periodic,
+        ))
+    }
+
+    fn read_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) -> Result<Vec<u8>> {
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("encoder:fluid_readback"),
+        });
+        readback::read_texture(device, queue, encoder, texture)
+    }
+
+    fn read_pressure(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<f32>> {
+        let pressure_texture =
+            &self.fields.pressure_textures[*self.last_pressure_index.lock().unwrap()];
+        let pressure_bytes = self.read_texture(device, queue, pressure_texture)?;
+        let pressure = match pressure_texture.format() {
+            wgpu::TextureFormat::R32Float => pressure_bytes
+                .chunks_exact(4)
+                .map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+                .collect(),
+            wgpu::TextureFormat::Rgba16Float => pressure_bytes
+                .chunks_exact(8)
+                .map(|texel| half::f16::from_le_bytes([texel[0], texel[1]]).to_f32())
+                .collect(),
+            other => panic!("unexpected pressure format: {other:?}"),
+        };
+        Ok(pressure)
+    }
+
+    /// Uploads the fluid fields and speed measurements captured with
+    /// `snapshot`. The fluid size must match.
+    pub fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        state: &snapshot::FluidState,
+    ) -> Result<()> {
+        let wgpu::Extent3d { width, height, .. } = self.fluid_size_3d;
+        if state.width != width || state.height != height {
+            return Err(Error::Snapshot(format!(
+                "the fluid size {}x{} doesn't match the current fluid size {}x{}",
+                state.width, state.height, width, height
+            )));
+        }
+        let pixel_count = (width * height) as usize;
+        if state.velocity.len() != 2 * pixel_count
+            || state.previous_velocity.len() != 2 * pixel_count
+            || state.pressure.len() != pixel_count
+            || state.dye.len() != 4 * pixel_count
+        {
+            return Err(Error::Snapshot(
+                "the fluid data doesn't match its size".to_string(),
+            ));
+        }
+
+        let to_texels = |velocity: &[f32]| {
+            velocity
+                .chunks_exact(2)
+                .map(|v| {
+                    [
+                        half::f16::from_f32(v[0]),
+                        half::f16::from_f32(v[1]),
+                        half::f16::ZERO,
+                        half::f16::ZERO,
+                    ]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let dye = state
+            .dye
+            .chunks_exact(4)
+            .map(|texel| [0, 1, 2, 3].map(|i| half::f16::from_f32(texel[i])))
+            .collect::<Vec<_>>();
+
+        self.write_velocity(queue, &to_texels(&state.velocity));
+        self.write_velocity_texture(
+            queue,
+            &self.fields.previous_velocity_texture,
+            &to_texels(&state.previous_velocity),
+        );
+        self.write_pressure(queue, &state.pressure);
+        self.set_dye(queue, &dye);
+
+        self.speed_probe.restore(device, &state.speed)
+    }
+
     pub fn solve_pressure<'cpass>(
         &'cpass self,
         queue: &wgpu::Queue,
@@ -1075,15 +2729,126 @@ impl Context {
         }
 
         let mut pressure_index = self.last_pressure_index.lock().unwrap();
-        let workgroup = self.get_workgroup_size();
-        cpass.set_pipeline(&self.pressure_pipeline);
-        cpass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        cpass.set_bind_group(1, &self.divergence_sample_bind_group, &[]);
 
-        for _ in 0..self.pressure_iterations {
-            cpass.set_bind_group(2, &self.pressure_bind_groups[*pressure_index], &[]);
-            cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
-            *pressure_index = 1 - *pressure_index;
+        match self.pressure_solver {
+            settings::PressureSolver::Jacobi => {
+                let workgroup = self.get_workgroup_size();
+                cpass.set_pipeline(&self.pressure_pipeline);
+                cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+                cpass.set_bind_group(1, &self.fields.divergence_sample_bind_group, &[]);
+
+                for _ in 0..self.pressure_iterations {
+                    cpass.set_bind_group(
+                        2,
+                        &self.fields.pressure_bind_groups[*pressure_index],
+                        &[],
+                    );
+                    cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+                    *pressure_index = 1 - *pressure_index;
+                }
+            }
+            settings::PressureSolver::Multigrid { cycles } => {
+                for _ in 0..cycles {
+                    self.run_v_cycle(cpass, &mut pressure_index);
+                }
+            }
+        }
+    }
+
+    // Smooth, then restrict the residual down through the levels, solving
+    // for a correction on each. Then add the corrections back on the way up,
+    // smoothing out the error the interpolation leaves behind.
+    fn run_v_cycle<'cpass>(
+        &'cpass self,
+        cpass: &mut wgpu::ComputePass<'cpass>,
+        pressure_index: &mut usize,
+    ) {
+        let levels = &self.fields.multigrid_levels;
+
+        // The ping-pong index of each level. The coarse corrections are
+        // cleared when restricting, which resets them to 0.
+        let mut indices = vec![0; levels.len() + 1];
+        indices[0] = *pressure_index;
+
+        self.smooth_pressure(cpass, 0, &mut indices[0], MULTIGRID_SMOOTHING_SWEEPS);
+
+        for (k, level) in levels.iter().enumerate() {
+            let finer = self.pressure_grid(k);
+            cpass.set_pipeline(&self.restrict_pressure_pipeline);
+            cpass.set_bind_group(0, finer.uniform_bind_group, &[]);
+            cpass.set_bind_group(1, finer.divergence_sample_bind_group, &[]);
+            cpass.set_bind_group(2, &level.restrict_bind_groups[indices[k]], &[]);
+            cpass.set_bind_group(3, &level.clear_bind_group, &[]);
+            dispatch_over(cpass, level.size);
+
+            let sweeps = if k + 1 == levels.len() {
+                MULTIGRID_COARSEST_SWEEPS
+            } else {
+                MULTIGRID_SMOOTHING_SWEEPS
+            };
+            self.smooth_pressure(cpass, k + 1, &mut indices[k + 1], sweeps);
+        }
+
+        // Keep the coarsest correction from drifting by a constant.
+        if let Some(coarsest) = levels.last() {
+            let index = &mut indices[levels.len()];
+            cpass.set_pipeline(&self.center_pressure_pipeline);
+            cpass.set_bind_group(0, &coarsest.uniform_bind_group, &[]);
+            cpass.set_bind_group(1, &coarsest.pressure_bind_groups[*index], &[]);
+            cpass.dispatch_workgroups(1, 1, 1);
+            *index = 1 - *index;
+        }
+
+        for (k, level) in levels.iter().enumerate().rev() {
+            let finer = self.pressure_grid(k);
+            cpass.set_pipeline(&self.prolongate_pressure_pipeline);
+            cpass.set_bind_group(0, &level.pressure_sample_bind_groups[indices[k + 1]], &[]);
+            cpass.set_bind_group(1, &finer.pressure_bind_groups[indices[k]], &[]);
+            dispatch_over(cpass, finer.size);
+            indices[k] = 1 - indices[k];
+
+            self.smooth_pressure(cpass, k, &mut indices[k], MULTIGRID_SMOOTHING_SWEEPS);
+        }
+
+        *pressure_index = indices[0];
+    }
+
+    fn smooth_pressure<'cpass>(
+        &'cpass self,
+        cpass: &mut wgpu::ComputePass<'cpass>,
+        level: usize,
+        index: &mut usize,
+        sweeps: u32,
+    ) {
+        let grid = self.pressure_grid(level);
+        cpass.set_pipeline(&self.smooth_pressure_pipeline);
+        cpass.set_bind_group(0, grid.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, grid.divergence_sample_bind_group, &[]);
+
+        for _ in 0..sweeps {
+            cpass.set_bind_group(2, &grid.pressure_bind_groups[*index], &[]);
+            dispatch_over(cpass, grid.size);
+            *index = 1 - *index;
+        }
+    }
+
+    fn pressure_grid(&self, level: usize) -> PressureGrid<'_> {
+        match level.checked_sub(1) {
+            None => PressureGrid {
+                size: self.fluid_size_3d,
+                uniform_bind_group: &self.fields.uniform_bind_group,
+                divergence_sample_bind_group: &self.fields.divergence_sample_bind_group,
+                pressure_bind_groups: &self.fields.pressure_bind_groups,
+            },
+            Some(index) => {
+                let level = &self.fields.multigrid_levels[index];
+                PressureGrid {
+                    size: level.size,
+                    uniform_bind_group: &level.uniform_bind_group,
+                    divergence_sample_bind_group: &level.divergence_sample_bind_group,
+                    pressure_bind_groups: &level.pressure_bind_groups,
+                }
+            }
         }
     }
 
@@ -1092,44 +2857,257 @@ impl Context {
         let mut velocity_index = self.last_velocity_index.lock().unwrap();
         let workgroup = self.get_workgroup_size();
         cpass.set_pipeline(&self.subtract_gradient_pipeline);
-        cpass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        cpass.set_bind_group(1, &self.pressure_bind_groups[*pressure_index], &[]);
-        cpass.set_bind_group(2, &self.velocity_bind_groups[*velocity_index], &[]);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.pressure_bind_groups[*pressure_index], &[]);
+        cpass.set_bind_group(2, &self.fields.velocity_bind_groups[*velocity_index], &[]);
         cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
         *velocity_index = 1 - *velocity_index;
     }
 
+    /// Keeps a copy of the velocity field as it is before the next fluid
+    /// step. Call before encoding the step.
+    pub fn save_previous_velocity(&self, encoder: &mut wgpu::CommandEncoder) {
+        let index = self.last_velocity_index.lock().unwrap();
+        encoder.copy_texture_to_texture(
+            self.fields.velocity_textures[*index].as_image_copy(),
+            self.fields.previous_velocity_texture.as_image_copy(),
+            self.fluid_size_3d,
+        );
+    }
+
+    /// Measures the fastest speed in the velocity field. The result shows up
+    /// in `max_speed` a couple of frames later; see `speed::Probe`.
+    pub fn measure_speed(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.speed_probe.measure(
+            encoder,
+            self.get_read_velocity_bind_group(),
+            self.fluid_size_3d,
+        );
+    }
+
+    /// Picks up finished speed measurements. Call once per frame, before
+    /// encoding.
+    pub fn poll_speed(&mut self) {
+        self.speed_probe.poll();
+    }
+
+    /// The fastest speed in the velocity field, in cells per unit of
+    /// timestep, from the latest finished measurement.
+    pub fn max_speed(&self) -> Option<f32> {
+        self.speed_probe.max_speed()
+    }
+
     pub fn get_fluid_size(&self) -> wgpu::Extent3d {
         self.fluid_size_3d
     }
 
     pub fn get_velocity_texture_view(&self) -> &wgpu::TextureView {
         let index = self.last_velocity_index.lock().unwrap();
-        &self.velocity_texture_views[*index]
+        &self.fields.velocity_texture_views[*index]
+    }
+
+    pub fn get_previous_velocity_texture_view(&self) -> &wgpu::TextureView {
+        &self.fields.previous_velocity_texture_view
     }
 
     pub fn get_advection_forward_texture_view(&self) -> &wgpu::TextureView {
-        &self.advection_forward_texture_view
+        &self.fields.advection_forward_texture_view
+    }
+
+    /// Marks the solid cells, one byte per cell in row order from the bottom
+    /// of the domain. Non-zero bytes are solid.
+    pub fn set_obstacles(&self, queue: &wgpu::Queue, cells: &[u8]) {
+        write_obstacles(queue, &self.fields.obstacle_texture, cells);
+
+        let mut size = self.fluid_size_3d;
+        let mut cells = Cow::Borrowed(cells);
+        for level in self.fields.multigrid_levels.iter() {
+            cells = Cow::Owned(obstacle::coarsen(&cells, size, level.size));
+            size = level.size;
+            write_obstacles(queue, &level.obstacle_texture, &cells);
+        }
+    }
+
+    /// Advects the dye through the current velocity field, then mixes in the
+    /// emitters. Does nothing unless the settings use the dye.
+    pub fn advect_dye<'cpass>(&'cpass self, cpass: &mut wgpu::ComputePass<'cpass>) {
+        if !self.dye_enabled {
+            return;
+        }
+
+        let velocity_index = self.last_velocity_index.lock().unwrap();
+        let velocity_bind_group = &self.fields.velocity_bind_groups[*velocity_index];
+        let workgroup = self.get_workgroup_size();
+
+        cpass.set_pipeline(&self.advect_dye_pipeline);
+        cpass.set_bind_group(0, &self.fields.uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.dye_advection_forward_bind_group, &[]);
+        cpass.set_bind_group(2, &self.advection_forward_direction_bind_group, &[]);
+        cpass.set_bind_group(3, velocity_bind_group, &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+
+        cpass.set_bind_group(1, &self.fields.dye_advection_reverse_bind_group, &[]);
+        cpass.set_bind_group(2, &self.advection_reverse_direction_bind_group, &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+
+        cpass.set_pipeline(&self.adjust_dye_advection_pipeline);
+        cpass.set_bind_group(1, &self.fields.dye_adjust_advection_bind_group, &[]);
+        cpass.set_bind_group(2, &self.fields.dye_adjust_bind_group, &[]);
+        cpass.set_bind_group(3, velocity_bind_group, &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+
+        cpass.set_pipeline(&self.inject_dye_pipeline);
+        cpass.set_bind_group(0, &self.dye_uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &self.fields.dye_inject_bind_group, &[]);
+        cpass.dispatch_workgroups(workgroup.0, workgroup.1, workgroup.2);
+    }
+
+    /// Replaces the dye with linear, premultiplied RGBA texels, in the same
+    /// order as `set_obstacles`.
+    pub fn set_dye(&self, queue: &wgpu::Queue, texels: &[[half::f16; 4]]) {
+        let size = self.fluid_size_3d;
+        debug_assert_eq!(texels.len(), (size.width * size.height) as usize);
+        queue.write_texture(
+            self.fields.dye_textures[0].as_image_copy(),
+            bytemuck::cast_slice(texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(8 * size.width),
+                rows_per_image: None,
+            },
+            size,
+        );
+    }
+
+    /// Sets the dye emitters for the following steps. `aspect` is the
+    /// domain's width over its height. Emitters past `MAX_DYE_EMITTERS` are
+    /// ignored.
+    pub fn set_dye_emitters(&mut self, queue: &wgpu::Queue, aspect: f32, emitters: &[DyeEmitter]) {
+        let count = emitters.len().min(MAX_DYE_EMITTERS);
+        self.dye_uniforms = DyeUniforms {
+            count: count as u32,
+            aspect,
+            timestep: self.fluid_uniforms.timestep,
+            _padding: 0.0,
+            emitters: [DyeEmitter::default(); MAX_DYE_EMITTERS],
+        };
+        self.dye_uniforms.emitters[..count].copy_from_slice(&emitters[..count]);
+
+        queue.write_buffer(
+            &self.dye_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.dye_uniforms),
+        );
+    }
+
+    pub fn get_dye_texture_view(&self) -> &wgpu::TextureView {
+        &self.fields.dye_texture_views[0]
+    }
+
+    pub fn get_obstacle_texture_view(&self) -> &wgpu::TextureView {
+        &self.fields.obstacle_texture_view
     }
 
     pub fn get_divergence_texture_view(&self) -> &wgpu::TextureView {
-        &self.divergence_texture_view
+        &self.fields.divergence_texture_view
+    }
+
+    pub fn get_vorticity_texture_view(&self) -> &wgpu::TextureView {
+        &self.fields.vorticity_texture_view
     }
 
     pub fn get_pressure_texture_view(&self) -> &wgpu::TextureView {
         let index = self.last_pressure_index.lock().unwrap();
-        &self.pressure_texture_views[*index]
+        &self.fields.pressure_texture_views[*index]
     }
 
     pub fn get_read_velocity_bind_group(&self) -> &wgpu::BindGroup {
         let index = self.last_velocity_index.lock().unwrap();
-        &self.velocity_bind_groups[*index]
+        &self.fields.velocity_bind_groups[*index]
     }
 
     pub fn get_write_velocity_bind_group(&self) -> &wgpu::BindGroup {
         let mut index = self.last_velocity_index.lock().unwrap();
         let curr_index = *index;
         *index = 1 - *index;
-        &self.velocity_bind_groups[curr_index]
+        &self.fields.velocity_bind_groups[curr_index]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uniforms_match_the_wgsl_layout() {
+        assert_eq!(std::mem::size_of::<FluidUniforms>(), 48);
+        assert_eq!(std::mem::size_of::<DyeEmitter>(), 32);
+        assert_eq!(
+            std::mem::size_of::<DyeUniforms>(),
+            16 + 32 * MAX_DYE_EMITTERS
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter; run with `cargo test -- --ignored`"]
+    fn dye_timestep_follows_the_substeps() {
+        let (device, queue, caps) =
+            crate::render::request_test_device().expect("no GPU adapter available");
+        let settings = Arc::new(Settings {
+            fluid_size: 32,
+            ..Default::default()
+        });
+        let mut fluid = Context::new(
+            &device,
+            &queue,
+            grid::ScalingRatio::new(171, 171),
+            caps,
+            &settings,
+        );
+        assert_eq!(fluid.dye_uniforms.timestep, settings.fluid_timestep);
+
+        fluid.set_substeps(&queue, &settings, 4);
+        assert_eq!(fluid.dye_uniforms.timestep, settings.fluid_timestep / 4.0);
+
+        let emitter = DyeEmitter::new([0.5, 0.5], 0.1, [1.0; 4], 1.0);
+        fluid.set_dye_emitters(&queue, 1.0, &[emitter]);
+        fluid.set_substeps(&queue, &settings, 2);
+        assert_eq!(fluid.dye_uniforms.count, 1);
+        assert_eq!(fluid.dye_uniforms.timestep, settings.fluid_timestep / 2.0);
+    }
+
+    #[test]
+    fn measures_the_pressure_residual() {
+        let divergence = [1.0, 0.0, -1.0];
+        let open = [0; 3];
+
+        // A zero field leaves all of the divergence as the residual.
+        assert_eq!(
+            relative_residual(&[0.0; 3], &divergence, &open, 3, 1, false),
+            1.0
+        );
+
+        // The edges reflect the pressure next to them, so a ramp solves it.
+        assert_eq!(
+            relative_residual(&[0.0, 1.0, 2.0], &divergence, &open, 3, 1, false),
+            0.0
+        );
+
+        // So does an obstacle, and the pressure inside it doesn't matter.
+        let solid = [0, 0, 255];
+        assert_eq!(
+            relative_residual(&[0.0, 1.0, 5.0], &[1.0, -1.0, 0.0], &solid, 3, 1, false),
+            0.0
+        );
+
+        // A periodic domain wraps the ramp's ends around to each other.
+        assert_eq!(
+            relative_residual(&[0.0, 1.0, 2.0], &divergence, &open, 3, 1, true),
+            2.0
+        );
+        assert_eq!(
+            relative_residual(&[1.0, 0.0, 0.0], &[-2.0, 1.0, 1.0], &open, 3, 1, true),
+            0.0
+        );
     }
 }
@@ -0,0 +1,216 @@
+use bytemuck::Zeroable;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// The number of impulses that can be splatted in a single fluid step. Any
+/// more than this and the oldest are dropped.
+pub const MAX_IMPULSES: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Impulse {
+    position: [f32; 2], // 0
+    velocity: [f32; 2], // 8
+    radius: f32,        // 16
+    _padding: [f32; 3], // 20
+                        // roundUp(16, 32) = 32
+}
+
+impl Impulse {
+    /// `position` is in texture space, `velocity` is in fluid units, and
+    /// `radius` is a fraction of the domain height.
+    pub fn new(position: [f32; 2], velocity: [f32; 2], radius: f32) -> Self {
+        Self {
+            position,
+            velocity,
+            radius,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ImpulseUniforms {
+    count: u32,                        // 0
+    aspect: f32,                       // 4
+    _padding: [f32; 2],                // 8
+    impulses: [Impulse; MAX_IMPULSES], // 16
+}
+
+/// Splats user input into the velocity field.
+///
+/// Impulses are queued with `push` and applied together in the next fluid
+/// step.
+pub struct Context {
+    pending: Vec<Impulse>,
+
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Context {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("uniform:impulses"),
+            contents: bytemuck::bytes_of(&ImpulseUniforms::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bind_group_layout:impulses"),
+            entries: &[
+                // uniforms
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let velocity_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:impulses_velocity"),
+                entries: &[
+                    // velocity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // out_velocity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba16Float,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:impulses"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &uniform_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pipeline_layout:splat_impulses"),
+            bind_group_layouts: &[Some(&bind_group_layout), Some(&velocity_bind_group_layout)],
+            immediate_size: 0,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:splat_impulses"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/splat_impulses.comp.wgsl"
+            )))),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("pipeline:splat_impulses"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self {
+            pending: Vec::with_capacity(MAX_IMPULSES),
+            uniform_buffer,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    pub fn push(&mut self, impulse: Impulse) {
+        if self.pending.len() == MAX_IMPULSES {
+            self.pending.remove(0);
+        }
+        self.pending.push(impulse);
+    }
+
+    /// Drops the impulses queued since the last step.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Upload the pending impulses and clear the queue. Returns `false` if
+    /// there is nothing to splat, in which case the uniforms are left alone.
+    ///
+    /// Buffer writes only land at the next submit, so call this at most once
+    /// per encoder.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, aspect: f32) -> bool {
+        if self.pending.is_empty() {
+            return false;
+        }
+
+        let mut uniforms = ImpulseUniforms {
+            count: self.pending.len() as u32,
+            aspect,
+            _padding: [0.0; 2],
+            impulses: [Impulse::default(); MAX_IMPULSES],
+        };
+        uniforms.impulses[..self.pending.len()].copy_from_slice(&self.pending);
+        self.pending.clear();
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        true
+    }
+
+    pub fn splat_into<'cpass>(
+        &'cpass self,
+        cpass: &mut wgpu::ComputePass<'cpass>,
+        target_texture_bind_group: &'cpass wgpu::BindGroup,
+        target_texture_size: wgpu::Extent3d,
+    ) {
+        cpass.set_pipeline(&self.pipeline);
+        cpass.set_bind_group(0, &self.bind_group, &[]);
+        cpass.set_bind_group(1, target_texture_bind_group, &[]);
+        cpass.dispatch_workgroups(
+            target_texture_size.width.div_ceil(16),
+            target_texture_size.height.div_ceil(16),
+            1,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uniforms_match_the_wgsl_layout() {
+        assert_eq!(std::mem::size_of::<Impulse>(), 32);
+        assert_eq!(
+            std::mem::size_of::<ImpulseUniforms>(),
+            16 + 32 * MAX_IMPULSES
+        );
+    }
+}
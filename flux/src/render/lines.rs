@@ -1,6 +1,11 @@
+use crate::color;
+use crate::error::{Error, Result};
 use crate::grid::Grid;
 use crate::render::view::ViewTransform;
-use crate::settings::{ColorMode, Settings};
+use crate::settings::{self, Settings};
+use crate::snapshot;
+
+use super::{address_mode, readback};
 
 use bytemuck::Zeroable;
 use std::borrow::Cow;
@@ -27,8 +32,44 @@ struct LineUniforms {
     color_mode: u32, // 44
 
     delta_time: f32, // 48
-    _padding: u32,   // 52
-                     // roundUp(52, 8) = 56
+
+    // How far the display is between the previous fluid step and the latest
+    // one, from 0 to 1.
+    velocity_blend: f32, // 52
+
+    // The space the gradient stops are in. See `ColorSpace`.
+    color_space: u32, // 56
+    _padding: u32,    // 60
+                      // roundUp(8, 64) = 64
+}
+
+// A stop of the gradient the lines are colored from by direction, as the
+// shader reads it. The stops are uploaded sorted by position, with their
+// colors converted into the space the gradient blends in.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct GradientStop {
+    color: [f32; 4],    // 0
+    position: f32,      // 16
+    interpolation: u32, // 20
+    _padding: [u32; 2], // 24
+                        // roundUp(16, 24) = 32
+}
+
+impl GradientStop {
+    fn sorted(stops: &[settings::GradientStop], color_space: settings::ColorSpace) -> Vec<Self> {
+        let mut stops = stops
+            .iter()
+            .map(|stop| Self {
+                color: color::to_space(stop.color, color_space),
+                position: stop.position,
+                interpolation: stop.interpolation.into(),
+                _padding: [0; 2],
+            })
+            .collect::<Vec<_>>();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        stops
+    }
 }
 
 impl LineUniforms {
@@ -50,6 +91,8 @@ impl LineUniforms {
             line_noise_blend_factor: 0.0,
             color_mode: settings.color_mode.clone().into(),
             delta_time: 1.0 / 60.0, // Initial value, will be updated every frame
+            velocity_blend: 1.0,
+            color_space: settings.gradient_color_space.into(),
             _padding: 0,
         }
     }
@@ -139,7 +182,9 @@ pub struct Context {
     line_uniform_buffer: wgpu::Buffer,
     line_buffers: Vec<wgpu::Buffer>,
 
+    // Reads the fluid fields, which wrap around with a periodic boundary.
     linear_sampler: wgpu::Sampler,
+    address_mode: wgpu::AddressMode,
     uniform_bind_group_layout: wgpu::BindGroupLayout,
     uniform_bind_group: wgpu::BindGroup,
     draw_uniform_bind_group: wgpu::BindGroup,
@@ -151,15 +196,21 @@ pub struct Context {
     pub color_mode: u32,
     color_texture_sampler: wgpu::Sampler,
     color_texture_view: wgpu::TextureView,
+    obstacle_texture_view: wgpu::TextureView,
+    previous_velocity_texture_view: wgpu::TextureView,
+    dye_texture_view: wgpu::TextureView,
     color_buffer: wgpu::Buffer,
     color_bind_group_layout: wgpu::BindGroupLayout,
     color_bind_group: wgpu::BindGroup,
+    dye_bind_group_layout: wgpu::BindGroupLayout,
+    dye_bind_group: wgpu::BindGroup,
 
     place_lines_pipeline: wgpu::ComputePipeline,
     resample_pipeline: wgpu::ComputePipeline,
     resample_bind_group_layout: wgpu::BindGroupLayout,
     draw_line_pipeline: wgpu::RenderPipeline,
     draw_endpoint_pipeline: wgpu::RenderPipeline,
+    draw_dye_pipeline: wgpu::RenderPipeline,
 }
 
 impl Context {
@@ -171,6 +222,29 @@ impl Context {
         grid: &Grid,
         settings: &Settings,
     ) {
+        let address_mode = address_mode(settings.boundary);
+        if address_mode != self.address_mode {
+            self.address_mode = address_mode;
+            self.linear_sampler = create_linear_sampler(device, address_mode);
+            self.uniform_bind_group = build_uniform_bind_group(
+                device,
+                &self.uniform_bind_group_layout,
+                &self.line_uniform_buffer,
+                &self.basepoints_buffer,
+                &self.target_basepoints_buffer,
+                &self.linear_sampler,
+                &self.color_texture_sampler,
+                &self.obstacle_texture_view,
+                &self.previous_velocity_texture_view,
+            );
+            self.dye_bind_group = build_dye_bind_group(
+                device,
+                &self.dye_bind_group_layout,
+                &self.dye_texture_view,
+                &self.linear_sampler,
+            );
+        }
+
         self.line_uniforms = {
             let mut new_line_uniforms = LineUniforms::new(screen_size, grid, settings);
             new_line_uniforms.line_noise_offset_1 = self.line_uniforms.line_noise_offset_1;
@@ -180,20 +254,8 @@ impl Context {
             new_line_uniforms
         };
 
-        if let ColorMode::Preset(preset) = settings.color_mode {
-            if let Some(color_wheel) = preset.to_color_wheel() {
-                self.color_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-                    label: Some("buffer:color"),
-                    size: 4 * (color_wheel.len() as u64),
-                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
-                    mapped_at_creation: false,
-                });
-
-                queue.write_buffer(&self.color_buffer, 0, bytemuck::cast_slice(&[color_wheel]));
-
-                self.color_mode = 1;
-                self.update_color_bindings(device, queue, None, None);
-            }
+        if let Some(stops) = settings.color_mode.gradient() {
+            self.set_gradient(device, queue, &stops, settings.gradient_color_space);
         }
 
         queue.write_buffer(
@@ -203,6 +265,25 @@ impl Context {
         );
     }
 
+    /// Colors the lines by direction from a gradient, blended in
+    /// `color_space`.
+    pub fn set_gradient(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        stops: &[settings::GradientStop],
+        color_space: settings::ColorSpace,
+    ) {
+        let color_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("buffer:color"),
+            contents: bytemuck::cast_slice(&GradientStop::sorted(stops, color_space)),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+        });
+
+        self.line_uniforms.color_space = color_space.into();
+        self.update_color_bindings(device, queue, None, Some(color_buffer));
+    }
+
     pub fn set_view_transform(&self, queue: &wgpu::Queue, view_transform: ViewTransform) {
         let view_matrix = ViewUniform::from(view_transform);
         queue.write_buffer(
@@ -228,36 +309,94 @@ impl Context {
             self.color_mode = 1;
         }
 
-        self.color_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:color"),
-            layout: &self.color_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.color_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &self.color_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-            ],
-        });
+        self.color_bind_group = build_color_bind_group(
+            device,
+            &self.color_bind_group_layout,
+            &self.color_texture_view,
+            &self.color_buffer,
+            &self.dye_texture_view,
+        );
 
         self.update_line_color_mode(device, queue);
     }
 
+    /// Points the lines at a new dye texture, e.g. after the fluid textures
+    /// are reallocated.
+    pub fn set_dye_texture_view(
+        &mut self,
+        device: &wgpu::Device,
+        dye_texture_view: &wgpu::TextureView,
+    ) {
+        self.dye_texture_view = dye_texture_view.clone();
+        self.color_bind_group = build_color_bind_group(
+            device,
+            &self.color_bind_group_layout,
+            &self.color_texture_view,
+            &self.color_buffer,
+            &self.dye_texture_view,
+        );
+        self.dye_bind_group = build_dye_bind_group(
+            device,
+            &self.dye_bind_group_layout,
+            &self.dye_texture_view,
+            &self.linear_sampler,
+        );
+    }
+
+    /// Points the lines at a new obstacle mask, e.g. after the fluid
+    /// textures are reallocated.
+    pub fn set_obstacle_texture_view(
+        &mut self,
+        device: &wgpu::Device,
+        obstacle_texture_view: &wgpu::TextureView,
+    ) {
+        self.obstacle_texture_view = obstacle_texture_view.clone();
+        self.uniform_bind_group = build_uniform_bind_group(
+            device,
+            &self.uniform_bind_group_layout,
+            &self.line_uniform_buffer,
+            &self.basepoints_buffer,
+            &self.target_basepoints_buffer,
+            &self.linear_sampler,
+            &self.color_texture_sampler,
+            &self.obstacle_texture_view,
+            &self.previous_velocity_texture_view,
+        );
+    }
+
+    /// Points the lines at a new copy of the previous fluid step, e.g. after
+    /// the fluid textures are reallocated.
+    pub fn set_previous_velocity_texture_view(
+        &mut self,
+        device: &wgpu::Device,
+        previous_velocity_texture_view: &wgpu::TextureView,
+    ) {
+        self.previous_velocity_texture_view = previous_velocity_texture_view.clone();
+        self.uniform_bind_group = build_uniform_bind_group(
+            device,
+            &self.uniform_bind_group_layout,
+            &self.line_uniform_buffer,
+            &self.basepoints_buffer,
+            &self.target_basepoints_buffer,
+            &self.linear_sampler,
+            &self.color_texture_sampler,
+            &self.obstacle_texture_view,
+            &self.previous_velocity_texture_view,
+        );
+    }
+
+    /// `velocity_blend` is how far to move from the previous fluid step to
+    /// the latest one when sampling the velocity, from 0 to 1.
     pub fn tick_line_uniforms(
         &mut self,
         _device: &wgpu::Device,
         queue: &wgpu::Queue,
         timestep: f32,
         elapsed_time: f32,
+        velocity_blend: f32,
     ) {
         self.line_uniforms.tick(timestep, elapsed_time);
+        self.line_uniforms.velocity_blend = velocity_blend;
 
         queue.write_buffer(
             &self.line_uniform_buffer,
@@ -338,7 +477,8 @@ impl Context {
             contents: bytemuck::cast_slice(&grid.basepoints),
             usage: wgpu::BufferUsages::VERTEX
                 | wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_DST,
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
         });
 
         // Buffer 0 receives the resampled state; buffer 1 is fully written by the
@@ -352,7 +492,8 @@ impl Context {
                     contents: bytemuck::cast_slice(&lines),
                     usage: wgpu::BufferUsages::VERTEX
                         | wgpu::BufferUsages::STORAGE
-                        | wgpu::BufferUsages::COPY_DST,
+                        | wgpu::BufferUsages::COPY_DST
+                        | wgpu::BufferUsages::COPY_SRC,
                 })
             })
             .collect::<Vec<_>>();
@@ -455,6 +596,8 @@ impl Context {
             &target_basepoints_buffer,
             &self.linear_sampler,
             &self.color_texture_sampler,
+            &self.obstacle_texture_view,
+            &self.previous_velocity_texture_view,
         );
 
         self.line_count = grid.line_count;
@@ -468,6 +611,7 @@ impl Context {
         self.target_basepoints_buffer = target_basepoints_buffer;
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -475,6 +619,9 @@ impl Context {
         screen_size: wgpu::Extent3d,
         grid: &Grid,
         settings: &Settings,
+        obstacle_texture_view: &wgpu::TextureView,
+        previous_velocity_texture_view: &wgpu::TextureView,
+        dye_texture_view: &wgpu::TextureView,
     ) -> Self {
         let line_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("buffer:vertices"),
@@ -509,7 +656,8 @@ impl Context {
             contents: bytemuck::cast_slice(&grid.basepoints),
             usage: wgpu::BufferUsages::VERTEX
                 | wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_DST,
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
         });
 
         let target_basepoints_buffer =
@@ -528,19 +676,14 @@ impl Context {
                     contents: bytemuck::cast_slice(&lines),
                     usage: wgpu::BufferUsages::VERTEX
                         | wgpu::BufferUsages::STORAGE
-                        | wgpu::BufferUsages::COPY_DST,
+                        | wgpu::BufferUsages::COPY_DST
+                        | wgpu::BufferUsages::COPY_SRC,
                 })
             })
             .collect::<Vec<_>>();
 
-        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("sampler:linear"),
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            ..Default::default()
-        });
+        let address_mode = address_mode(settings.boundary);
+        let linear_sampler = create_linear_sampler(device, address_mode);
 
         let color_texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("sampler:color_texture"),
@@ -604,6 +747,28 @@ impl Context {
                         },
                         count: None,
                     },
+                    // obstacle_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // previous_velocity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
@@ -615,6 +780,8 @@ impl Context {
             &target_basepoints_buffer,
             &linear_sampler,
             &color_texture_sampler,
+            obstacle_texture_view,
+            previous_velocity_texture_view,
         );
 
         // The draw pipelines only need the uniforms (binding 0). A dedicated
@@ -745,7 +912,7 @@ impl Context {
 
         let color_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("buffer:color"),
-            size: 4 * 4,
+            size: std::mem::size_of::<GradientStop>() as u64,
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
@@ -774,26 +941,57 @@ impl Context {
                         },
                         count: None,
                     },
+                    // dye_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             });
-        let color_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &color_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&color_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &color_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-            ],
-        });
+        let color_bind_group = build_color_bind_group(
+            device,
+            &color_bind_group_layout,
+            &color_texture_view,
+            &color_buffer,
+            dye_texture_view,
+        );
+
+        let dye_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:dye"),
+                entries: &[
+                    // dye_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // dye_sampler
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let dye_bind_group = build_dye_bind_group(
+            device,
+            &dye_bind_group_layout,
+            dye_texture_view,
+            &linear_sampler,
+        );
 
         // TODO: reuse layout from fluid
         let velocity_bind_group_layout =
@@ -839,9 +1037,10 @@ impl Context {
 
         let place_lines_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:place_lines"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/place_lines.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/place_lines.comp.wgsl"
+            )))),
         });
 
         let place_lines_pipeline =
@@ -936,9 +1135,10 @@ impl Context {
 
         let resample_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:resample_lines"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/resample_lines.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/resample_lines.comp.wgsl"
+            )))),
         });
 
         let resample_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
@@ -962,7 +1162,10 @@ impl Context {
 
         let draw_line_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:draw_line"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../../shader/line.wgsl"))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/line.wgsl"
+            )))),
         });
 
         let vertex_buffer_layouts = [
@@ -1035,9 +1238,10 @@ impl Context {
 
         let draw_endpoint_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:draw_endpoint"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/endpoint.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/endpoint.wgsl"
+            )))),
         });
 
         // TODO: reuse draw_line layout
@@ -1064,6 +1268,51 @@ impl Context {
                 cache: None,
             });
 
+        let draw_dye_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:draw_dye"),
+                bind_group_layouts: &[
+                    Some(&draw_uniform_bind_group_layout),
+                    Some(&view_uniform_bind_group_layout),
+                    Some(&dye_bind_group_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let draw_dye_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:draw_dye"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/dye.wgsl"
+            )))),
+        });
+
+        let draw_dye_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("pipeline:draw_dye"),
+            layout: Some(&draw_dye_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &draw_dye_shader,
+                entry_point: Some("main_vs"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &draw_dye_shader,
+                entry_point: Some("main_fs"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: swapchain_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: Default::default(),
+            cache: None,
+        });
+
         let work_group_count = ((grid.line_count as f32) / 64.0).ceil() as u32;
 
         let mut lines = Self {
@@ -1084,6 +1333,7 @@ impl Context {
             line_buffers,
 
             linear_sampler,
+            address_mode,
             color_texture_sampler,
             uniform_bind_group_layout,
             uniform_bind_group,
@@ -1095,15 +1345,21 @@ impl Context {
 
             color_mode: line_uniforms.color_mode,
             color_texture_view,
+            obstacle_texture_view: obstacle_texture_view.clone(),
+            previous_velocity_texture_view: previous_velocity_texture_view.clone(),
+            dye_texture_view: dye_texture_view.clone(),
             color_buffer,
             color_bind_group_layout,
             color_bind_group,
+            dye_bind_group_layout,
+            dye_bind_group,
 
             place_lines_pipeline,
             resample_pipeline,
             resample_bind_group_layout,
             draw_line_pipeline,
             draw_endpoint_pipeline,
+            draw_dye_pipeline,
         };
 
         // TODO: optimize this away
@@ -1112,6 +1368,86 @@ impl Context {
         lines
     }
 
+    /// Reads the current line state back from the GPU. Blocks until the copy
+    /// completes. Native targets only.
+    pub fn snapshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<snapshot::LinesState> {
+        let lines = readback::read_buffer(device, queue, &self.line_buffers[self.frame_num])?;
+        let basepoints = readback::read_buffer(device, queue, &self.basepoints_buffer)?;
+
+        Ok(snapshot::LinesState {
+            columns: self.columns,
+            rows: self.rows,
+            noise_offset_1: self.line_uniforms.line_noise_offset_1,
+            noise_offset_2: self.line_uniforms.line_noise_offset_2,
+            noise_blend_factor: self.line_uniforms.line_noise_blend_factor,
+            lines: to_f32s(&lines),
+            basepoints: to_f32s(&basepoints),
+        })
+    }
+
+    /// Returns every line to the state it starts in, so the lines grow back
+    /// from their basepoints. Any basepoint glide is skipped to the end.
+    pub fn reset_lines(&mut self, queue: &wgpu::Queue, grid: &Grid) {
+        let lines = vec![Line::zeroed(); self.line_count as usize];
+        for line_buffer in self.line_buffers.iter() {
+            queue.write_buffer(line_buffer, 0, bytemuck::cast_slice(&lines));
+        }
+        for basepoints_buffer in [&self.basepoints_buffer, &self.target_basepoints_buffer] {
+            queue.write_buffer(basepoints_buffer, 0, bytemuck::cast_slice(&grid.basepoints));
+        }
+
+        self.line_uniforms.line_noise_offset_1 = 0.0;
+        self.line_uniforms.line_noise_offset_2 = 0.0;
+        self.line_uniforms.line_noise_blend_factor = 0.0;
+        queue.write_buffer(
+            &self.line_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.line_uniforms]),
+        );
+    }
+
+    /// Uploads line state captured with `snapshot`. The grid must match.
+    pub fn restore(&mut self, queue: &wgpu::Queue, state: &snapshot::LinesState) -> Result<()> {
+        if state.columns != self.columns || state.rows != self.rows {
+            return Err(Error::Snapshot(format!(
+                "the grid {}x{} doesn't match the current grid {}x{}",
+                state.columns, state.rows, self.columns, self.rows
+            )));
+        }
+        let line_floats = std::mem::size_of::<Line>() / std::mem::size_of::<f32>();
+        if state.lines.len() != line_floats * self.line_count as usize
+            || state.basepoints.len() != 2 * self.line_count as usize
+        {
+            return Err(Error::Snapshot(
+                "the line data doesn't match its grid".to_string(),
+            ));
+        }
+
+        for line_buffer in self.line_buffers.iter() {
+            queue.write_buffer(line_buffer, 0, bytemuck::cast_slice(&state.lines));
+        }
+        queue.write_buffer(
+            &self.basepoints_buffer,
+            0,
+            bytemuck::cast_slice(&state.basepoints),
+        );
+
+        self.line_uniforms.line_noise_offset_1 = state.noise_offset_1;
+        self.line_uniforms.line_noise_offset_2 = state.noise_offset_2;
+        self.line_uniforms.line_noise_blend_factor = state.noise_blend_factor;
+        queue.write_buffer(
+            &self.line_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.line_uniforms]),
+        );
+
+        Ok(())
+    }
+
     pub fn place_lines<'cpass>(
         &'cpass mut self,
         cpass: &mut wgpu::ComputePass<'cpass>,
@@ -1146,6 +1482,80 @@ impl Context {
         rpass.set_vertex_buffer(2, self.endpoint_vertex_buffer.slice(..));
         rpass.draw(0..6, 0..self.line_count);
     }
+
+    /// Draws the dye over the whole target. Call before drawing the lines.
+    pub fn draw_dye<'rpass>(&'rpass self, rpass: &mut wgpu::RenderPass<'rpass>) {
+        rpass.set_pipeline(&self.draw_dye_pipeline);
+        rpass.set_bind_group(0, &self.draw_uniform_bind_group, &[]);
+        rpass.set_bind_group(1, &self.view_uniform_bind_group, &[]);
+        rpass.set_bind_group(2, &self.dye_bind_group, &[]);
+        rpass.draw(0..6, 0..1);
+    }
+}
+
+fn create_linear_sampler(device: &wgpu::Device, address_mode: wgpu::AddressMode) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("sampler:linear"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        ..Default::default()
+    })
+}
+
+fn build_color_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    color_texture_view: &wgpu::TextureView,
+    color_buffer: &wgpu::Buffer,
+    dye_texture_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bind_group:color"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(color_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: color_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            // dye_texture
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(dye_texture_view),
+            },
+        ],
+    })
+}
+
+fn build_dye_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    dye_texture_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bind_group:dye"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(dye_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -1157,6 +1567,8 @@ fn build_uniform_bind_group(
     target_basepoints_buffer: &wgpu::Buffer,
     linear_sampler: &wgpu::Sampler,
     color_texture_sampler: &wgpu::Sampler,
+    obstacle_texture_view: &wgpu::TextureView,
+    previous_velocity_texture_view: &wgpu::TextureView,
 ) -> wgpu::BindGroup {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: Some("bind_group:uniforms"),
@@ -1187,10 +1599,27 @@ fn build_uniform_bind_group(
                 binding: 4,
                 resource: target_basepoints_buffer.as_entire_binding(),
             },
+            // obstacle_texture
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(obstacle_texture_view),
+            },
+            // previous_velocity_texture
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(previous_velocity_texture_view),
+            },
         ],
     })
 }
 
+fn to_f32s(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
 fn get_line_scale_factor(width: f32, height: f32) -> f32 {
     let aspect_ratio = width / height;
     let p = 1.0 / aspect_ratio;
@@ -1216,3 +1645,41 @@ pub static ENDPOINT_VERTICES: [f32; 12] = [
     -1.0,  1.0,
      1.0,  1.0,
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::{ColorSpace, GradientInterpolation};
+
+    #[test]
+    fn uniforms_match_the_wgsl_layout() {
+        assert_eq!(std::mem::size_of::<LineUniforms>(), 64);
+        assert_eq!(std::mem::size_of::<GradientStop>(), 32);
+    }
+
+    #[test]
+    fn uploads_gradient_stops_in_order() {
+        let stop = |position, interpolation| settings::GradientStop {
+            color: [position; 4],
+            position,
+            interpolation,
+        };
+        let sorted = GradientStop::sorted(
+            &[
+                stop(0.75, GradientInterpolation::Constant),
+                stop(0.0, GradientInterpolation::Linear),
+                stop(0.5, GradientInterpolation::Smooth),
+            ],
+            ColorSpace::Srgb,
+        );
+
+        assert_eq!(
+            sorted
+                .iter()
+                .map(|stop| (stop.position, stop.interpolation))
+                .collect::<Vec<_>>(),
+            vec![(0.0, 0), (0.5, 1), (0.75, 2)]
+        );
+        assert_eq!(sorted[1].color, [0.5; 4]);
+    }
+}
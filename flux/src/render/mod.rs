@@ -1,17 +1,30 @@
 use std::borrow::Cow;
 
-use crate::BackendCaps;
+use crate::{settings, BackendCaps};
 
 pub mod color;
 pub mod fluid;
+pub mod impulse;
 pub mod lines;
 pub mod noise;
+pub mod offscreen;
+pub(crate) mod readback;
+pub mod speed;
 pub mod texture;
 pub mod view;
 
 pub use view::ScreenViewport;
 pub use view::ViewTransform;
 
+/// How samplers of anything laid over the fluid domain treat the edges:
+/// periodic domains sample across them, walled ones clamp to them.
+pub(crate) fn address_mode(boundary: settings::Boundary) -> wgpu::AddressMode {
+    match boundary {
+        settings::Boundary::Periodic => wgpu::AddressMode::Repeat,
+        settings::Boundary::FreeSlip | settings::Boundary::NoSlip => wgpu::AddressMode::ClampToEdge,
+    }
+}
+
 /// Returns WGSL source with `r32float` / `rg32float` write-only storage texture
 /// formats rewritten to `rgba16float` when `FLOAT32_FILTERABLE` isn't available.
 ///
@@ -51,6 +64,32 @@ pub(crate) fn downgrade_float_storage(
     Cow::Owned(patched)
 }
 
+/// A device for the tests that need a GPU, or `None` without an adapter.
+/// Sticks to the features every backend has.
+#[cfg(test)]
+pub(crate) fn request_test_device() -> Option<(wgpu::Device, wgpu::Queue, BackendCaps)> {
+    pollster::block_on(async {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .ok()?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                required_limits: adapter.limits(),
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+
+        let caps = BackendCaps {
+            float32_filterable: false,
+        };
+        Some((device, queue, caps))
+    })
+}
+
 // pub struct GraphicsContext {
 //     pub device: wgpu::Device,
 //     pub queue: wgpu::Queue,
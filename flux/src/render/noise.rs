@@ -1,6 +1,7 @@
-use crate::{grid, rng, settings, BackendCaps};
+use crate::error::{Error, Result};
+use crate::{grid, rng, settings, snapshot, BackendCaps};
 
-use super::downgrade_float_storage;
+use super::{address_mode, downgrade_float_storage};
 
 use std::borrow::Cow;
 use std::sync::Arc;
@@ -9,6 +10,7 @@ use wgpu::util::DeviceExt;
 pub struct NoiseGenerator {
     elapsed_time: f32, // TODO: reset
 
+    size: u32,
     texture: wgpu::Texture,
     texture_view: wgpu::TextureView,
     texture_format: wgpu::TextureFormat,
@@ -21,8 +23,13 @@ pub struct NoiseGenerator {
 
     uniform_buffer: wgpu::Buffer,
     channel_buffer: wgpu::Buffer,
+    // Wraps around with a periodic boundary, like the noise itself.
+    linear_sampler: wgpu::Sampler,
+    address_mode: wgpu::AddressMode,
+    bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
     push_constants_buffer: wgpu::Buffer,
+    inject_noise_bind_group_layout: wgpu::BindGroupLayout,
     inject_noise_bind_group: wgpu::BindGroup,
 
     generate_noise_pipeline: wgpu::ComputePipeline,
@@ -31,7 +38,7 @@ pub struct NoiseGenerator {
 
 impl NoiseGenerator {
     pub fn resize(&mut self, device: &wgpu::Device, size: u32, scaling_ratio: grid::ScalingRatio) {
-        if scaling_ratio == self.scaling_ratio {
+        if size == self.size && scaling_ratio == self.scaling_ratio {
             return;
         }
 
@@ -39,22 +46,70 @@ impl NoiseGenerator {
             size * scaling_ratio.rounded_x(),
             size * scaling_ratio.rounded_y(),
         );
-        let size = wgpu::Extent3d {
+        let extent = wgpu::Extent3d {
             width,
             height,
             depth_or_array_layers: 1,
         };
 
-        let (texture, texture_view) = create_texture(device, &size, self.texture_format);
+        let (texture, texture_view) = create_texture(device, &extent, self.texture_format);
 
+        self.size = size;
         self.scaling_ratio = scaling_ratio;
         self.texture = texture;
         self.texture_view = texture_view;
+
+        // Both bind groups hold the noise texture.
+        self.rebuild_bind_groups(device);
     }
 
-    pub fn update(&mut self, new_settings: &settings::Settings) {
-        self.uniforms.multiplier = new_settings.noise_multiplier;
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        new_settings: &settings::Settings,
+        rng: &mut rng::Rng,
+    ) {
+        self.uniforms = NoiseUniforms::new(new_settings);
         self.channel_settings = new_settings.noise_channels.to_vec();
+
+        let address_mode = address_mode(new_settings.boundary);
+        if address_mode != self.address_mode {
+            self.address_mode = address_mode;
+            self.linear_sampler = create_linear_sampler(device, address_mode);
+            self.rebuild_bind_groups(device);
+        }
+
+        if self.channels.len() == self.channel_settings.len() {
+            return;
+        }
+
+        // Existing channels keep their offsets, so the surviving octaves don't
+        // jump. New channels start fresh.
+        self.channels.truncate(self.channel_settings.len());
+        for channel_settings in &self.channel_settings[self.channels.len()..] {
+            self.channels
+                .push(NoiseChannel::new(self.scaling_ratio, channel_settings, rng));
+        }
+
+        self.channel_buffer = create_channel_buffer(device, &self.channels);
+        self.rebuild_bind_groups(device);
+    }
+
+    fn rebuild_bind_groups(&mut self, device: &wgpu::Device) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.channel_buffer,
+            &self.texture_view,
+        );
+        self.inject_noise_bind_group = create_inject_noise_bind_group(
+            device,
+            &self.inject_noise_bind_group_layout,
+            &self.push_constants_buffer,
+            &self.texture_view,
+            &self.linear_sampler,
+        );
     }
 
     pub fn update_buffers(&mut self, queue: &wgpu::Queue, timestep: f32) {
@@ -86,6 +141,54 @@ impl NoiseGenerator {
         );
     }
 
+    pub fn snapshot(&self) -> snapshot::NoiseState {
+        snapshot::NoiseState {
+            elapsed_time: self.elapsed_time,
+            channels: self
+                .channels
+                .iter()
+                .map(|channel| snapshot::NoiseChannelState {
+                    offset_1: channel.offset_1,
+                    offset_2: channel.offset_2,
+                    blend_factor: channel.blend_factor,
+                })
+                .collect(),
+        }
+    }
+
+    /// Starts the noise over from a fresh set of random channel offsets, as
+    /// when it was built.
+    ///
+    /// The channel buffer is rewritten on the next update_buffers.
+    pub fn reset(&mut self, rng: &mut rng::Rng) {
+        self.elapsed_time = 0.0;
+        self.channels = self
+            .channel_settings
+            .iter()
+            .map(|channel_settings| NoiseChannel::new(self.scaling_ratio, channel_settings, rng))
+            .collect();
+    }
+
+    // The channel buffer is rewritten from these on the next update_buffers.
+    pub fn restore(&mut self, state: &snapshot::NoiseState) -> Result<()> {
+        if state.channels.len() != self.channels.len() {
+            return Err(Error::Snapshot(format!(
+                "it has {} noise channels, but {} are configured",
+                state.channels.len(),
+                self.channels.len()
+            )));
+        }
+
+        self.elapsed_time = state.elapsed_time;
+        for (channel, channel_state) in self.channels.iter_mut().zip(state.channels.iter()) {
+            channel.offset_1 = channel_state.offset_1;
+            channel.offset_2 = channel_state.offset_2;
+            channel.blend_factor = channel_state.blend_factor;
+        }
+
+        Ok(())
+    }
+
     pub fn generate<'cpass>(&'cpass self, cpass: &mut wgpu::ComputePass<'cpass>) {
         let workgroup = (
             self.texture.size().width / 16,
@@ -152,6 +255,7 @@ impl NoiseGeneratorBuilder {
         device: &wgpu::Device,
         _queue: &wgpu::Queue,
         caps: BackendCaps,
+        rng: &mut rng::Rng,
     ) -> NoiseGenerator {
         log::info!("🎛 Generating noise");
 
@@ -159,7 +263,7 @@ impl NoiseGeneratorBuilder {
         let channels = self
             .channels
             .iter()
-            .map(|channel| NoiseChannel::new(self.scaling_ratio, channel))
+            .map(|channel| NoiseChannel::new(self.scaling_ratio, channel, rng))
             .collect::<Vec<_>>();
 
         let (width, height) = (
@@ -186,14 +290,8 @@ impl NoiseGeneratorBuilder {
 
         let (texture, texture_view) = create_texture(device, &size, noise_format);
 
-        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("sampler:linear"),
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            ..Default::default()
-        });
+        let address_mode = address_mode(self.settings.boundary);
+        let linear_sampler = create_linear_sampler(device, address_mode);
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("uniform:noise"),
@@ -201,11 +299,7 @@ impl NoiseGeneratorBuilder {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let channel_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("storage:noise_channels"),
-            contents: bytemuck::cast_slice(&channels),
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
+        let channel_buffer = create_channel_buffer(device, &channels);
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("bind_group_layout:noise"),
@@ -264,36 +358,13 @@ impl NoiseGeneratorBuilder {
             ],
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:noise"),
-            layout: &bind_group_layout,
-            entries: &[
-                // wgpu::BindGroupEntry {
-                //     binding: 2,
-                //     resource: wgpu::BindingResource::Sampler(&linear_sampler),
-                // },
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &uniform_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &channel_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-            ],
-        });
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &channel_buffer,
+            &texture_view,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("pipeline_layout:generate_noise"),
@@ -304,7 +375,7 @@ impl NoiseGeneratorBuilder {
         let generate_noise_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader:generate_noise"),
             source: wgpu::ShaderSource::Wgsl(downgrade_float_storage(
-                include_str!("../../shader/generate_noise.comp.wgsl"),
+                include_str!(concat!(env!("OUT_DIR"), "/shader/generate_noise.comp.wgsl")),
                 caps,
             )),
         });
@@ -400,28 +471,13 @@ impl NoiseGeneratorBuilder {
                 ],
             });
 
-        let inject_noise_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Inject noise bind group"),
-            layout: &inject_noise_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &push_constants_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&linear_sampler),
-                },
-            ],
-        });
+        let inject_noise_bind_group = create_inject_noise_bind_group(
+            device,
+            &inject_noise_bind_group_layout,
+            &push_constants_buffer,
+            &texture_view,
+            &linear_sampler,
+        );
 
         let inject_noise_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -435,9 +491,10 @@ impl NoiseGeneratorBuilder {
 
         let inject_noise_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Inject noise shader"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../shader/inject_noise.comp.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/inject_noise.comp.wgsl"
+            )))),
         });
 
         let inject_noise_pipeline =
@@ -459,11 +516,16 @@ impl NoiseGeneratorBuilder {
 
             uniform_buffer,
             channel_buffer,
+            size: self.size,
             scaling_ratio: self.scaling_ratio,
             texture,
             texture_view,
             texture_format: noise_format,
+            linear_sampler,
+            address_mode,
+            bind_group_layout,
             bind_group,
+            inject_noise_bind_group_layout,
             inject_noise_bind_group,
             push_constants_buffer,
 
@@ -488,6 +550,7 @@ fn create_texture(
         view_formats: &[],
         usage: wgpu::TextureUsages::TEXTURE_BINDING
             | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
             | wgpu::TextureUsages::COPY_DST,
     });
 
@@ -499,6 +562,95 @@ fn create_texture(
     (texture, texture_view)
 }
 
+fn create_linear_sampler(device: &wgpu::Device, address_mode: wgpu::AddressMode) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("sampler:linear"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        ..Default::default()
+    })
+}
+
+fn create_channel_buffer(device: &wgpu::Device, channels: &[NoiseChannel]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("storage:noise_channels"),
+        contents: bytemuck::cast_slice(channels),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    channel_buffer: &wgpu::Buffer,
+    texture_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bind_group:noise"),
+        layout,
+        entries: &[
+            // wgpu::BindGroupEntry {
+            //     binding: 2,
+            //     resource: wgpu::BindingResource::Sampler(&linear_sampler),
+            // },
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: channel_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+        ],
+    })
+}
+
+fn create_inject_noise_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    push_constants_buffer: &wgpu::Buffer,
+    texture_view: &wgpu::TextureView,
+    linear_sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Inject noise bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: push_constants_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(linear_sampler),
+            },
+        ],
+    })
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct NoiseChannel {
@@ -514,13 +666,17 @@ pub struct NoiseChannel {
 impl NoiseChannel {
     const BLEND_THRESHOLD: f32 = 1000.0;
 
-    pub fn new(scaling_ratio: grid::ScalingRatio, channel_settings: &settings::Noise) -> Self {
+    pub fn new(
+        scaling_ratio: grid::ScalingRatio,
+        channel_settings: &settings::Noise,
+        rng: &mut rng::Rng,
+    ) -> Self {
         Self {
             scale: [
                 channel_settings.scale * scaling_ratio.x(),
                 channel_settings.scale * scaling_ratio.y(),
             ],
-            offset_1: Self::BLEND_THRESHOLD * rng::gen::<f32>(),
+            offset_1: Self::BLEND_THRESHOLD * rng.gen::<f32>(),
             offset_2: 0.0,
             blend_factor: 0.0,
             multiplier: channel_settings.multiplier,
@@ -553,14 +709,93 @@ impl NoiseChannel {
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct NoiseUniforms {
     multiplier: f32, // 0
-    _padding: [u32; 3],
+    periodic: u32,   // 4
+    _padding: [u32; 2],
 }
 
 impl NoiseUniforms {
     fn new(settings: &settings::Settings) -> Self {
         Self {
             multiplier: settings.noise_multiplier,
-            _padding: [0, 0, 0],
+            periodic: (settings.boundary == settings::Boundary::Periodic).into(),
+            _padding: [0, 0],
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::readback;
+
+    #[test]
+    #[ignore = "needs a GPU adapter; run with `cargo test -- --ignored`"]
+    fn wraps_around_with_a_periodic_boundary() {
+        let (device, queue, caps) =
+            super::super::request_test_device().expect("no GPU adapter available");
+        // Smooth enough that any seam would stand out.
+        let settings = Arc::new(settings::Settings {
+            boundary: settings::Boundary::Periodic,
+            noise_channels: vec![settings::Noise {
+                scale: 2.8,
+                multiplier: 1.0,
+                offset_increment: 0.001,
+            }],
+            ..Default::default()
+        });
+        let mut builder = NoiseGeneratorBuilder::new(64, grid::ScalingRatio::new(1, 1), &settings);
+        for channel in &settings.noise_channels {
+            builder.add_channel(channel);
+        }
+        let noise = builder.build(
+            &device,
+            &queue,
+            caps,
+            &mut rng::Rng::from_seed(&Some("noise".to_string())),
+        );
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            noise.generate(&mut cpass);
+        }
+        let bytes = readback::read_texture(&device, &queue, encoder, &noise.texture).unwrap();
+        let field = match noise.texture_format {
+            wgpu::TextureFormat::Rg32Float => bytes
+                .chunks_exact(8)
+                .map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+                .collect::<Vec<_>>(),
+            wgpu::TextureFormat::Rgba16Float => bytes
+                .chunks_exact(8)
+                .map(|texel| half::f16::from_le_bytes([texel[0], texel[1]]).to_f32())
+                .collect(),
+            other => panic!("unexpected noise format: {other:?}"),
+        };
+
+        // Going across an edge is no bigger a step than going between any two
+        // neighbouring texels inside the field.
+        let size = noise.texture.size();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let at = |x: usize, y: usize| field[y * width + x];
+        let mut inner_step = 0.0_f32;
+        let mut edge_step = 0.0_f32;
+        for y in 0..height {
+            for x in 1..width {
+                inner_step = inner_step.max((at(x, y) - at(x - 1, y)).abs());
+            }
+            edge_step = edge_step.max((at(0, y) - at(width - 1, y)).abs());
+        }
+        for x in 0..width {
+            for y in 1..height {
+                inner_step = inner_step.max((at(x, y) - at(x, y - 1)).abs());
+            }
+            edge_step = edge_step.max((at(x, 0) - at(x, height - 1)).abs());
+        }
+
+        assert!(inner_step > 0.0);
+        assert!(
+            edge_step <= inner_step,
+            "the field jumps by {edge_step} across the edges, but at most {inner_step} inside"
+        );
+    }
+}
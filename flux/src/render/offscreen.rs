@@ -0,0 +1,72 @@
+use super::readback;
+use crate::error::{Error, Result};
+
+use image::RgbaImage;
+
+/// A render target owned by Flux itself, for rendering without a window
+/// surface. Frames rendered into it can be read back to the CPU as an
+/// `RgbaImage`.
+pub struct Target {
+    texture: wgpu::Texture,
+    texture_view: wgpu::TextureView,
+    size: wgpu::Extent3d,
+}
+
+impl Target {
+    // Flux writes sRGB-encoded values, so a non-sRGB format keeps the bytes we
+    // read back identical to what a window surface would display.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture:offscreen"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("view:offscreen"),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            texture_view,
+            size,
+        }
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        self.size
+    }
+
+    pub fn texture_view(&self) -> &wgpu::TextureView {
+        &self.texture_view
+    }
+
+    /// Submits `encoder` and blocks until the target can be read back.
+    /// Native targets only.
+    pub fn read_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: wgpu::CommandEncoder,
+    ) -> Result<RgbaImage> {
+        let pixels = readback::read_texture(device, queue, encoder, &self.texture)?;
+
+        RgbaImage::from_raw(self.size.width, self.size.height, pixels).ok_or_else(|| {
+            Error::Readback("the readback buffer doesn't match the target size".to_string())
+        })
+    }
+}
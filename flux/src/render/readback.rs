@@ -0,0 +1,144 @@
+// Blocking GPU → CPU copies.
+//
+// These submit their own command buffer and wait on the device, so they only
+// work on native targets. On the web, map callbacks are driven by the browser's
+// event loop.
+
+use crate::error::{Error, Result};
+
+/// Copies `texture` into a staging buffer, submits `encoder` and returns the
+/// texel data with the row padding stripped.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>> {
+    let size = texture.size();
+    let bytes_per_texel = texture
+        .format()
+        .block_copy_size(None)
+        .ok_or_else(|| Error::Readback(format!("can't read {:?} textures", texture.format())))?;
+    let bytes_per_row = bytes_per_texel * size.width;
+    let padded_bytes_per_row = padded_bytes_per_row(bytes_per_row);
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("buffer:texture_readback"),
+        size: u64::from(padded_bytes_per_row) * u64::from(size.height),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let padded = map_and_read(device, &buffer)?;
+    Ok(unpad_rows(
+        &padded,
+        bytes_per_row,
+        padded_bytes_per_row,
+        size.height,
+    ))
+}
+
+/// Copies the whole of `source` into a staging buffer and returns its
+/// contents.
+pub fn read_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    source: &wgpu::Buffer,
+) -> Result<Vec<u8>> {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("buffer:buffer_readback"),
+        size: source.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("encoder:buffer_readback"),
+    });
+    encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, source.size());
+    queue.submit(Some(encoder.finish()));
+
+    map_and_read(device, &buffer)
+}
+
+fn map_and_read(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Result<Vec<u8>> {
+    let slice = buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .map_err(|err| Error::Readback(err.to_string()))?;
+    rx.recv()
+        .map_err(|err| Error::Readback(err.to_string()))?
+        .map_err(|err| Error::Readback(err.to_string()))?;
+
+    let bytes = slice
+        .get_mapped_range()
+        .map_err(|err| Error::Readback(err.to_string()))?
+        .to_vec();
+    buffer.unmap();
+
+    Ok(bytes)
+}
+
+// Buffer copies need each row to start on a `COPY_BYTES_PER_ROW_ALIGNMENT`
+// boundary.
+fn padded_bytes_per_row(bytes_per_row: u32) -> u32 {
+    bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+fn unpad_rows(padded: &[u8], bytes_per_row: u32, padded_bytes_per_row: u32, rows: u32) -> Vec<u8> {
+    padded
+        .chunks_exact(padded_bytes_per_row as usize)
+        .take(rows as usize)
+        .flat_map(|row| &row[..bytes_per_row as usize])
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pads_rows_to_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(4), 256);
+        assert_eq!(padded_bytes_per_row(256), 256);
+        assert_eq!(padded_bytes_per_row(4 * 65), 512);
+        assert_eq!(padded_bytes_per_row(4 * 1920), 7680);
+    }
+
+    #[test]
+    fn strips_row_padding() {
+        let (bytes_per_row, padded_bytes_per_row, rows) = (12, 256, 3);
+        let mut padded = vec![0xff; (padded_bytes_per_row * rows) as usize];
+        for row in 0..rows {
+            let start = (row * padded_bytes_per_row) as usize;
+            for i in 0..bytes_per_row as usize {
+                padded[start + i] = row as u8;
+            }
+        }
+
+        let pixels = unpad_rows(&padded, bytes_per_row, padded_bytes_per_row, rows);
+        assert_eq!(pixels.len(), (bytes_per_row * rows) as usize);
+        assert!(pixels[..12].iter().all(|&b| b == 0));
+        assert!(pixels[12..24].iter().all(|&b| b == 1));
+        assert!(pixels[24..].iter().all(|&b| b == 2));
+    }
+}
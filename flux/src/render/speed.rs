@@ -0,0 +1,368 @@
+use crate::error::{Error, Result};
+use crate::settings::AdaptiveTimestep;
+use crate::snapshot;
+
+use super::readback;
+
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+/// How many substeps keep the fastest fluid within `adaptive.cfl` cells per
+/// substep, given the speed measured in cells per unit of timestep.
+///
+/// A speed that isn't finite takes the most substeps allowed.
+pub fn substep_count(max_speed: f32, timestep: f32, adaptive: &AdaptiveTimestep) -> u32 {
+    let cells_per_step = max_speed * timestep / adaptive.cfl;
+    if !cells_per_step.is_finite() {
+        return adaptive.max_substeps;
+    }
+
+    (cells_per_step.ceil() as u32).clamp(1, adaptive.max_substeps)
+}
+
+/// Whether the velocity field has filled with NaN or infinity, or has run
+/// away so fast that the fluid would cross the whole domain in one step.
+pub fn has_blown_up(max_speed: f32, timestep: f32, fluid_size: wgpu::Extent3d) -> bool {
+    let domain = fluid_size.width.max(fluid_size.height) as f32;
+    !max_speed.is_finite() || max_speed * timestep > domain
+}
+
+// The readback buffer can't be copied into while it's mapped, and can only be
+// mapped once the copy has been submitted. The host owns the encoder, so each
+// stage waits for the next call to `poll`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ReadbackState {
+    Idle,
+    Copied,
+    Mapping,
+    Mapped,
+    Failed,
+}
+
+/// Measures the fastest speed in the velocity field on the GPU.
+///
+/// The result is read back without stalling, so it lags the field by a
+/// couple of frames.
+pub struct Probe {
+    max_speed_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    readback_state: Arc<Mutex<ReadbackState>>,
+    max_speed: Option<f32>,
+    // Set when the field changed under a measurement still in flight.
+    discard_pending: bool,
+
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Probe {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let max_speed_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("buffer:max_speed"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let readback_buffer = create_readback_buffer(device, false);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bind_group_layout:max_speed"),
+            entries: &[
+                // max_speed
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // Matches the fluid's velocity layout, so its bind groups can be
+        // reused.
+        let velocity_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:max_speed_velocity"),
+                entries: &[
+                    // velocity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // out_velocity_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba16Float,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:max_speed"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: max_speed_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pipeline_layout:measure_speed"),
+            bind_group_layouts: &[Some(&bind_group_layout), Some(&velocity_bind_group_layout)],
+            immediate_size: 0,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:measure_speed"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(concat!(
+                env!("OUT_DIR"),
+                "/shader/measure_speed.comp.wgsl"
+            )))),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("pipeline:measure_speed"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self {
+            max_speed_buffer,
+            readback_buffer,
+            readback_state: Arc::new(Mutex::new(ReadbackState::Idle)),
+            max_speed: None,
+            discard_pending: false,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// The latest speed read back, if any.
+    pub fn max_speed(&self) -> Option<f32> {
+        self.max_speed
+    }
+
+    /// Forgets the latest speed and any measurement still being read back,
+    /// for when the velocity field has been replaced.
+    pub fn discard(&mut self) {
+        self.max_speed = None;
+        self.discard_pending = *self.readback_state.lock().unwrap() != ReadbackState::Idle;
+    }
+
+    /// Captures the latest speed and any measurement still being read back.
+    /// Blocks until the GPU has finished reading back. Native targets only.
+    pub fn snapshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<snapshot::SpeedState> {
+        // Assumes the readback finishes before the next frame, as it does
+        // once this has waited on the GPU.
+        let frames_left = match *self.readback_state.lock().unwrap() {
+            ReadbackState::Copied => 2,
+            ReadbackState::Mapping | ReadbackState::Mapped => 1,
+            ReadbackState::Idle | ReadbackState::Failed => 0,
+        };
+
+        let pending = if frames_left == 0 {
+            None
+        } else {
+            // Nothing is measured while a readback is in flight, so the
+            // measurement is still in `max_speed_buffer`.
+            let bytes = readback::read_buffer(device, queue, &self.max_speed_buffer)?;
+            let max_speed = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            Some(snapshot::PendingSpeed {
+                max_speed: (!self.discard_pending).then_some(max_speed),
+                frames_left,
+            })
+        };
+
+        Ok(snapshot::SpeedState {
+            max_speed: self.max_speed,
+            pending,
+        })
+    }
+
+    /// Restores measurements captured with `snapshot`, so that a pending one
+    /// lands on the same frame as it would have originally.
+    pub fn restore(&mut self, device: &wgpu::Device, state: &snapshot::SpeedState) -> Result<()> {
+        if let Some(pending) = state.pending {
+            if !(1..=2).contains(&pending.frames_left) {
+                return Err(Error::Snapshot(format!(
+                    "a speed measurement can't be {} frames from landing",
+                    pending.frames_left
+                )));
+            }
+        }
+
+        // Start over with a new buffer, holding the pending measurement as if
+        // it had been copied or mapped already. A readback still in flight
+        // finishes into the old buffer and state, and is forgotten.
+        let readback_buffer = create_readback_buffer(device, true);
+        let readback_state = match state.pending {
+            None => ReadbackState::Idle,
+            Some(pending) => {
+                let max_speed = pending.max_speed.unwrap_or(0.0);
+                readback_buffer
+                    .slice(..)
+                    .get_mapped_range_mut()
+                    .map_err(|err| Error::Snapshot(err.to_string()))?
+                    .copy_from_slice(&max_speed.to_le_bytes());
+                if pending.frames_left == 1 {
+                    ReadbackState::Mapped
+                } else {
+                    ReadbackState::Copied
+                }
+            }
+        };
+        if readback_state != ReadbackState::Mapped {
+            readback_buffer.unmap();
+        }
+
+        self.readback_buffer = readback_buffer;
+        self.readback_state = Arc::new(Mutex::new(readback_state));
+        self.max_speed = state.max_speed;
+        self.discard_pending = state
+            .pending
+            .is_some_and(|pending| pending.max_speed.is_none());
+
+        Ok(())
+    }
+
+    /// Moves the readback along. Call once per frame, before encoding, after
+    /// the previous frame's encoder has been submitted.
+    pub fn poll(&mut self) {
+        let mut state = self.readback_state.lock().unwrap();
+        match *state {
+            ReadbackState::Copied => {
+                *state = ReadbackState::Mapping;
+                let readback_state = Arc::clone(&self.readback_state);
+                self.readback_buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |result| {
+                        *readback_state.lock().unwrap() = match result {
+                            Ok(()) => ReadbackState::Mapped,
+                            Err(err) => {
+                                log::warn!("Failed to read back the fluid speed: {}", err);
+                                ReadbackState::Failed
+                            }
+                        };
+                    });
+            }
+            ReadbackState::Mapped => {
+                if self.discard_pending {
+                    self.discard_pending = false;
+                } else if let Ok(bytes) = self.readback_buffer.slice(..).get_mapped_range() {
+                    let bits = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    self.max_speed = Some(f32::from_bits(bits));
+                }
+                self.readback_buffer.unmap();
+                *state = ReadbackState::Idle;
+            }
+            ReadbackState::Failed => {
+                self.discard_pending = false;
+                self.readback_buffer.unmap();
+                *state = ReadbackState::Idle;
+            }
+            ReadbackState::Idle | ReadbackState::Mapping => (),
+        }
+    }
+
+    /// Measures the velocity field bound by `velocity_bind_group`, unless the
+    /// last measurement is still being read back.
+    pub fn measure(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        velocity_bind_group: &wgpu::BindGroup,
+        size: wgpu::Extent3d,
+    ) {
+        let mut state = self.readback_state.lock().unwrap();
+        if *state != ReadbackState::Idle {
+            return;
+        }
+
+        encoder.clear_buffer(&self.max_speed_buffer, 0, None);
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("flux::measure_speed"),
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &self.bind_group, &[]);
+            cpass.set_bind_group(1, velocity_bind_group, &[]);
+            cpass.dispatch_workgroups(size.width.div_ceil(16), size.height.div_ceil(16), 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.max_speed_buffer, 0, &self.readback_buffer, 0, 4);
+
+        *state = ReadbackState::Copied;
+    }
+}
+
+fn create_readback_buffer(device: &wgpu::Device, mapped_at_creation: bool) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("buffer:max_speed_readback"),
+        size: 4,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_the_fluid_within_the_cfl_limit() {
+        let adaptive = AdaptiveTimestep {
+            cfl: 1.0,
+            max_substeps: 8,
+        };
+
+        // Slow fluid takes a single step.
+        assert_eq!(substep_count(0.0, 0.1, &adaptive), 1);
+        assert_eq!(substep_count(10.0, 0.1, &adaptive), 1);
+        // 2.5 cells per step needs 3 substeps.
+        assert_eq!(substep_count(25.0, 0.1, &adaptive), 3);
+        // Capped, even if the fluid has blown up.
+        assert_eq!(substep_count(1000.0, 0.1, &adaptive), 8);
+        assert_eq!(substep_count(f32::INFINITY, 0.1, &adaptive), 8);
+        assert_eq!(substep_count(f32::NAN, 0.1, &adaptive), 8);
+    }
+
+    #[test]
+    fn flags_non_finite_and_runaway_speeds() {
+        let size = wgpu::Extent3d {
+            width: 256,
+            height: 128,
+            depth_or_array_layers: 1,
+        };
+
+        assert!(!has_blown_up(0.0, 0.1, size));
+        assert!(!has_blown_up(2560.0, 0.1, size));
+        assert!(has_blown_up(2561.0, 0.1, size));
+        assert!(has_blown_up(f32::INFINITY, 0.1, size));
+        assert!(has_blown_up(f32::NAN, 0.1, size));
+    }
+}
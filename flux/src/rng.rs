@@ -3,36 +3,88 @@
 //
 // We want to have the option of seeding our RNGs to generate determentistic
 // output for testing.
+//
+// Each `Flux` instance owns an `Rng`, so instances running side by side never
+// disturb each other. Every random choice an instance makes is drawn from it,
+// which makes `Settings.seed` fully determine the output. Snapshots carry the
+// generator's position in its stream, so a restored instance goes on to make
+// the same choices as the original. Those choices are:
+//
+// - The starting offset of each noise channel, in `NoiseChannel::new`. Drawn
+//   when the noise is built, when a settings update adds channels, and on
+//   `Flux::reset`.
+// - The seed itself, when none is given. It comes from the thread's
+//   OS-seeded RNG, and is the only thing that does.
+//
+// The shaders are deterministic.
+
+use crate::snapshot;
 
 use rand::distr::{Alphanumeric, StandardUniform};
 use rand::prelude::*;
 use rand_pcg::Pcg32;
 use rand_seeder::Seeder;
-use std::cell::RefCell;
-use std::thread_local;
 
-thread_local!(
-    static FLUX_RNG: RefCell<Pcg32> = {
-        let rng = Pcg32::from_rng(&mut rand::rng());
-        RefCell::new(rng)
+pub struct Rng(Pcg32);
+
+impl Rng {
+    pub fn from_seed(optional_seed: &Option<String>) -> Self {
+        let seed = optional_seed.as_ref().cloned().unwrap_or_else(|| {
+            rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect()
+        });
+
+        Self(Seeder::from(seed).into_rng())
+    }
+
+    pub fn gen<T>(&mut self) -> T
+    where
+        StandardUniform: Distribution<T>,
+    {
+        self.0.random::<T>()
+    }
+
+    pub fn snapshot(&self) -> snapshot::RngState {
+        snapshot::RngState {
+            state: self.0.state(),
+            stream: self.0.stream(),
+        }
+    }
+
+    pub fn restore(&mut self, state: &snapshot::RngState) {
+        self.0 = Pcg32::from_state(state.state, state.stream);
     }
-);
-
-pub fn init_from_seed(optional_seed: &Option<String>) {
-    let seed = optional_seed.as_ref().cloned().unwrap_or_else(|| {
-        rand::rng()
-            .sample_iter(&Alphanumeric)
-            .take(32)
-            .map(char::from)
-            .collect()
-    });
-
-    FLUX_RNG.with(|rng| rng.replace(Seeder::from(seed).into_rng()));
 }
 
-pub fn gen<T>() -> T
-where
-    StandardUniform: Distribution<T>,
-{
-    FLUX_RNG.with(|rng| rng.borrow_mut().random::<T>())
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn instances_with_a_seed_agree() {
+        let seed = Some("flux".to_string());
+        let mut a = Rng::from_seed(&seed);
+        let mut b = Rng::from_seed(&seed);
+
+        let first = a.gen::<f32>();
+        // Seeding another instance doesn't disturb the first.
+        let _ = Rng::from_seed(&Some("other".to_string())).gen::<f32>();
+        assert_eq!(b.gen::<f32>(), first);
+        assert_eq!(a.gen::<u32>(), b.gen::<u32>());
+    }
+
+    #[test]
+    fn resumes_from_a_snapshot() {
+        let mut rng = Rng::from_seed(&Some("flux".to_string()));
+        let _ = rng.gen::<u64>();
+        let state = rng.snapshot();
+        let expected = rng.gen::<u64>();
+
+        let mut restored = Rng::from_seed(&None);
+        restored.restore(&state);
+        assert_eq!(restored.gen::<u64>(), expected);
+    }
 }
@@ -1,21 +1,53 @@
+use crate::error::Error;
+
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::ops::RangeInclusive;
+
+/// The current version of the settings format.
+///
+/// Bump this whenever a field is renamed or restructured, and add a step to
+/// `MIGRATIONS` that upgrades documents from the previous version.
+pub const VERSION: u32 = 2;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Settings {
+    /// The format version. Use `Settings::from_json` or `Settings::from_value`
+    /// to read stored settings, so older versions get migrated.
+    pub version: u32,
     pub mode: Mode,
+    /// Seeds everything random in an instance, so the same seed and settings
+    /// always give the same animation. `None` picks a random seed. Read when
+    /// an instance is created, not on updates; use `Flux::reset` to reseed.
     pub seed: Option<String>,
 
     pub fluid_size: u32,
     pub fluid_frame_rate: f32,
     pub fluid_timestep: f32,
+    /// Splits each fluid step into substeps when the fluid moves fast, so it
+    /// stays stable. `None` always takes a single step.
+    pub adaptive_timestep: Option<AdaptiveTimestep>,
     pub viscosity: f32,
     pub velocity_dissipation: f32,
+    pub advection: Advection,
+    pub boundary: Boundary,
     pub pressure_mode: PressureMode,
     pub diffusion_iterations: u32,
     pub pressure_iterations: u32,
+    pub pressure_solver: PressureSolver,
+    /// The strength of the vorticity confinement force, which feeds energy
+    /// back into small swirls. Zero turns it off.
+    pub vorticity: f32,
+    /// How quickly the dye fades, per second.
+    pub dye_dissipation: f32,
+    /// Sources that keep adding dye to the fluid.
+    pub dye_emitters: Vec<DyeEmitter>,
 
     pub color_mode: ColorMode,
+    /// The color space gradients and color wheel presets blend their stops
+    /// in.
+    pub gradient_color_space: ColorSpace,
 
     pub line_length: f32,
     pub line_width: f32,
@@ -26,22 +58,35 @@ pub struct Settings {
 
     pub noise_multiplier: f32,
     pub noise_channels: Vec<Noise>,
+
+    /// How an image set with `Flux::set_obstacle_mask` is turned into solid
+    /// cells.
+    pub obstacle_mask: ObstacleMask,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
+            version: VERSION,
             mode: Mode::Normal,
             seed: None,
             fluid_size: 128,
             fluid_frame_rate: 60.0,
             fluid_timestep: 1.0 / 60.0,
+            adaptive_timestep: None,
             viscosity: 5.0,
             velocity_dissipation: 0.0,
+            advection: Advection::MacCormack,
+            boundary: Boundary::FreeSlip,
             pressure_mode: PressureMode::ClearWith(0.0),
             diffusion_iterations: 3,
             pressure_iterations: 19,
+            pressure_solver: PressureSolver::Jacobi,
+            vorticity: 0.0,
+            dye_dissipation: 0.1,
+            dye_emitters: Vec::new(),
             color_mode: ColorMode::Preset(ColorPreset::Original),
+            gradient_color_space: ColorSpace::Srgb,
             line_length: 450.0,
             line_width: 9.0,
             line_begin_offset: 0.4,
@@ -66,18 +111,631 @@ impl Default for Settings {
                     offset_increment: 0.001 * 12.0,
                 },
             ],
+            obstacle_mask: ObstacleMask::default(),
         }
     }
 }
 
+// Each step upgrades a settings document by one version: `MIGRATIONS[n]`
+// turns version `n + 1` into version `n + 2`. Version 1 is the format from
+// before the `version` field was added, so documents without one are
+// version 1.
+const MIGRATIONS: [fn(&mut Map<String, Value>); VERSION as usize - 1] = [migrate_v1_to_v2];
+
+// `ColorMode::ImageFile` was a bare path before it gained preprocessing
+// options.
+fn migrate_v1_to_v2(settings: &mut Map<String, Value>) {
+    if let Some(Value::Object(color_mode)) = settings.get_mut("colorMode") {
+        if let Some(path @ Value::String(_)) = color_mode.get_mut("ImageFile") {
+            let mut image_file = Map::new();
+            image_file.insert("path".to_string(), path.take());
+            *path = Value::Object(image_file);
+        }
+    }
+}
+
+/// Upgrades a settings document of any older version to the current one.
+pub fn migrate(mut value: Value) -> Result<Value, Error> {
+    let settings = value
+        .as_object_mut()
+        .ok_or_else(|| Error::ParseSettings("expected a JSON object".to_string()))?;
+
+    let version = match settings.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|&version| version > 0)
+            .ok_or_else(|| Error::ParseSettings(format!("invalid version: {}", version)))?,
+    };
+    if version > VERSION {
+        return Err(Error::UnsupportedSettingsVersion(version));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        let from = index + 1;
+        log::debug!("Migrating settings from version {} to {}", from, from + 1);
+        migration(settings);
+    }
+    settings.insert("version".to_string(), Value::from(VERSION));
+
+    Ok(value)
+}
+
+impl Settings {
+    /// Whether anything reads the dye, so the fluid needs to advect it.
+    pub fn uses_dye(&self) -> bool {
+        self.mode == Mode::Dye || self.color_mode == ColorMode::Dye
+    }
+
+    /// Whether anything reads the curl of the velocity field.
+    pub fn uses_vorticity(&self) -> bool {
+        self.vorticity > 0.0 || self.mode == Mode::DebugVorticity
+    }
+
+    /// Parses a settings document of any version, migrating it to the
+    /// current one.
+    pub fn from_json(json: &str) -> Result<Settings, Error> {
+        let value =
+            serde_json::from_str(json).map_err(|err| Error::ParseSettings(err.to_string()))?;
+        Self::from_value(value)
+    }
+
+    /// Like `from_json`, for a document that has already been parsed.
+    pub fn from_value(value: Value) -> Result<Settings, Error> {
+        serde_json::from_value(migrate(value)?).map_err(|err| Error::ParseSettings(err.to_string()))
+    }
+}
+
+// The accepted range for each numeric setting. Shared by `validate` and
+// `clamped`, so the two can't drift apart.
+const FLUID_SIZE: RangeInclusive<u32> = 16..=1024;
+// The fluid passes are dispatched in 16x16 workgroups.
+const FLUID_SIZE_MULTIPLE: u32 = 16;
+const FLUID_FRAME_RATE: RangeInclusive<f32> = 1.0..=1000.0;
+const FLUID_TIMESTEP: RangeInclusive<f32> = 0.0001..=1.0;
+const CFL: RangeInclusive<f32> = 0.1..=10.0;
+const MAX_SUBSTEPS: RangeInclusive<u32> = 1..=16;
+// `FluidUniforms` divides by the viscosity.
+const VISCOSITY: RangeInclusive<f32> = 0.001..=1000.0;
+const VELOCITY_DISSIPATION: RangeInclusive<f32> = 0.0..=10.0;
+const PRESSURE: RangeInclusive<f32> = -1000.0..=1000.0;
+const DIFFUSION_ITERATIONS: RangeInclusive<u32> = 0..=100;
+const PRESSURE_ITERATIONS: RangeInclusive<u32> = 0..=200;
+const MULTIGRID_CYCLES: RangeInclusive<u32> = 1..=20;
+const VORTICITY: RangeInclusive<f32> = 0.0..=10.0;
+const DYE_DISSIPATION: RangeInclusive<f32> = 0.0..=10.0;
+const DYE_EMITTER_POSITION: RangeInclusive<f32> = 0.0..=1.0;
+const DYE_EMITTER_RADIUS: RangeInclusive<f32> = 0.001..=1.0;
+const DYE_EMITTER_COLOR: RangeInclusive<f32> = 0.0..=1.0;
+const DYE_EMITTER_RATE: RangeInclusive<f32> = 0.0..=100.0;
+const LINE_LENGTH: RangeInclusive<f32> = 0.0..=2000.0;
+const LINE_WIDTH: RangeInclusive<f32> = 0.0..=100.0;
+const LINE_BEGIN_OFFSET: RangeInclusive<f32> = 0.0..=1.0;
+const LINE_VARIANCE: RangeInclusive<f32> = 0.0..=1.0;
+const GRID_SPACING: RangeInclusive<u32> = 4..=1000;
+const VIEW_SCALE: RangeInclusive<f32> = 0.1..=10.0;
+const NOISE_MULTIPLIER: RangeInclusive<f32> = 0.0..=10.0;
+const NOISE_SCALE: RangeInclusive<f32> = 0.01..=1000.0;
+const NOISE_CHANNEL_MULTIPLIER: RangeInclusive<f32> = 0.0..=10.0;
+const NOISE_OFFSET_INCREMENT: RangeInclusive<f32> = 0.0..=1.0;
+const OBSTACLE_THRESHOLD: RangeInclusive<f32> = 0.0..=1.0;
+const GRADIENT_POSITION: RangeInclusive<f32> = 0.0..=1.0;
+const GRADIENT_COLOR: RangeInclusive<f32> = 0.0..=1.0;
+const IMAGE_SIZE: RangeInclusive<u32> = 1..=4096;
+const IMAGE_BLACK_LEVEL: RangeInclusive<f32> = 0.0..=1.0;
+const IMAGE_SATURATION: RangeInclusive<f32> = 0.0..=4.0;
+const IMAGE_CONTRAST: RangeInclusive<f32> = 0.0..=4.0;
+const IMAGE_GAMMA: RangeInclusive<f32> = 0.1..=10.0;
+const IMAGE_BLUR: RangeInclusive<f32> = 0.0..=32.0;
+
+/// A setting that is out of range, named by its JSON field path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl Settings {
+    /// Checks every setting against its accepted range. Returns all the
+    /// problems found, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        check_u32(&mut errors, "fluidSize", self.fluid_size, FLUID_SIZE);
+        if !self.fluid_size.is_multiple_of(FLUID_SIZE_MULTIPLE) {
+            errors.push(FieldError {
+                field: "fluidSize".to_string(),
+                message: format!(
+                    "{} is not a multiple of {}",
+                    self.fluid_size, FLUID_SIZE_MULTIPLE
+                ),
+            });
+        }
+        check_f32(
+            &mut errors,
+            "fluidFrameRate",
+            self.fluid_frame_rate,
+            FLUID_FRAME_RATE,
+        );
+        check_f32(
+            &mut errors,
+            "fluidTimestep",
+            self.fluid_timestep,
+            FLUID_TIMESTEP,
+        );
+        if let Some(adaptive) = self.adaptive_timestep {
+            check_f32(&mut errors, "adaptiveTimestep.cfl", adaptive.cfl, CFL);
+            check_u32(
+                &mut errors,
+                "adaptiveTimestep.maxSubsteps",
+                adaptive.max_substeps,
+                MAX_SUBSTEPS,
+            );
+        }
+        check_f32(&mut errors, "viscosity", self.viscosity, VISCOSITY);
+        check_f32(
+            &mut errors,
+            "velocityDissipation",
+            self.velocity_dissipation,
+            VELOCITY_DISSIPATION,
+        );
+        if let PressureMode::ClearWith(pressure) = self.pressure_mode {
+            check_f32(&mut errors, "pressureMode.ClearWith", pressure, PRESSURE);
+        }
+        check_u32(
+            &mut errors,
+            "diffusionIterations",
+            self.diffusion_iterations,
+            DIFFUSION_ITERATIONS,
+        );
+        check_u32(
+            &mut errors,
+            "pressureIterations",
+            self.pressure_iterations,
+            PRESSURE_ITERATIONS,
+        );
+        if let PressureSolver::Multigrid { cycles } = self.pressure_solver {
+            check_u32(
+                &mut errors,
+                "pressureSolver.Multigrid.cycles",
+                cycles,
+                MULTIGRID_CYCLES,
+            );
+        }
+        check_f32(&mut errors, "vorticity", self.vorticity, VORTICITY);
+        check_f32(
+            &mut errors,
+            "dyeDissipation",
+            self.dye_dissipation,
+            DYE_DISSIPATION,
+        );
+        if self.dye_emitters.len() > MAX_DYE_EMITTERS {
+            errors.push(FieldError {
+                field: "dyeEmitters".to_string(),
+                message: format!("more than {} emitters", MAX_DYE_EMITTERS),
+            });
+        }
+        for (i, emitter) in self.dye_emitters.iter().enumerate() {
+            let field = |name: &str| format!("dyeEmitters[{}].{}", i, name);
+            for (j, value) in emitter.position.iter().enumerate() {
+                check_f32(
+                    &mut errors,
+                    &field(&format!("position[{}]", j)),
+                    *value,
+                    DYE_EMITTER_POSITION,
+                );
+            }
+            check_f32(
+                &mut errors,
+                &field("radius"),
+                emitter.radius,
+                DYE_EMITTER_RADIUS,
+            );
+            for (j, value) in emitter.color.iter().enumerate() {
+                check_f32(
+                    &mut errors,
+                    &field(&format!("color[{}]", j)),
+                    *value,
+                    DYE_EMITTER_COLOR,
+                );
+            }
+            check_f32(&mut errors, &field("rate"), emitter.rate, DYE_EMITTER_RATE);
+        }
+
+        if let ColorMode::Gradient(stops) = &self.color_mode {
+            if stops.is_empty() || stops.len() > MAX_GRADIENT_STOPS {
+                errors.push(FieldError {
+                    field: "colorMode.Gradient".to_string(),
+                    message: format!("needs 1 to {} stops", MAX_GRADIENT_STOPS),
+                });
+            }
+            for (i, stop) in stops.iter().enumerate() {
+                let field = |name: &str| format!("colorMode.Gradient[{}].{}", i, name);
+                for (j, value) in stop.color.iter().enumerate() {
+                    check_f32(
+                        &mut errors,
+                        &field(&format!("color[{}]", j)),
+                        *value,
+                        GRADIENT_COLOR,
+                    );
+                }
+                check_f32(
+                    &mut errors,
+                    &field("position"),
+                    stop.position,
+                    GRADIENT_POSITION,
+                );
+            }
+        }
+
+        let image_options = match &self.color_mode {
+            ColorMode::ImageFile { options, .. } => Some(("ImageFile", options)),
+            ColorMode::ImagePalette { options, .. } => Some(("ImagePalette", options)),
+            _ => None,
+        };
+        if let Some((variant, options)) = image_options {
+            let field = |name: &str| format!("colorMode.{}.options.{}", variant, name);
+            check_u32(&mut errors, &field("width"), options.width, IMAGE_SIZE);
+            check_u32(&mut errors, &field("height"), options.height, IMAGE_SIZE);
+            check_f32(
+                &mut errors,
+                &field("blackLevel"),
+                options.black_level,
+                IMAGE_BLACK_LEVEL,
+            );
+            check_f32(
+                &mut errors,
+                &field("saturation"),
+                options.saturation,
+                IMAGE_SATURATION,
+            );
+            check_f32(
+                &mut errors,
+                &field("contrast"),
+                options.contrast,
+                IMAGE_CONTRAST,
+            );
+            check_f32(&mut errors, &field("gamma"), options.gamma, IMAGE_GAMMA);
+            check_f32(&mut errors, &field("blur"), options.blur, IMAGE_BLUR);
+        }
+
+        check_f32(&mut errors, "lineLength", self.line_length, LINE_LENGTH);
+        check_f32(&mut errors, "lineWidth", self.line_width, LINE_WIDTH);
+        check_f32(
+            &mut errors,
+            "lineBeginOffset",
+            self.line_begin_offset,
+            LINE_BEGIN_OFFSET,
+        );
+        check_f32(
+            &mut errors,
+            "lineVariance",
+            self.line_variance,
+            LINE_VARIANCE,
+        );
+        check_u32(&mut errors, "gridSpacing", self.grid_spacing, GRID_SPACING);
+        check_f32(&mut errors, "viewScale", self.view_scale, VIEW_SCALE);
+
+        check_f32(
+            &mut errors,
+            "noiseMultiplier",
+            self.noise_multiplier,
+            NOISE_MULTIPLIER,
+        );
+        if self.noise_channels.is_empty() {
+            errors.push(FieldError {
+                field: "noiseChannels".to_string(),
+                message: "needs at least one channel".to_string(),
+            });
+        }
+        for (i, channel) in self.noise_channels.iter().enumerate() {
+            let field = |name: &str| format!("noiseChannels[{}].{}", i, name);
+            check_f32(&mut errors, &field("scale"), channel.scale, NOISE_SCALE);
+            check_f32(
+                &mut errors,
+                &field("multiplier"),
+                channel.multiplier,
+                NOISE_CHANNEL_MULTIPLIER,
+            );
+            check_f32(
+                &mut errors,
+                &field("offsetIncrement"),
+                channel.offset_increment,
+                NOISE_OFFSET_INCREMENT,
+            );
+        }
+
+        check_f32(
+            &mut errors,
+            "obstacleMask.threshold",
+            self.obstacle_mask.threshold,
+            OBSTACLE_THRESHOLD,
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns a copy with every setting coerced into its accepted range.
+    /// Values that aren't numbers at all fall back to their defaults.
+    pub fn clamped(&self) -> Settings {
+        let default = Settings::default();
+        let default_channel = &default.noise_channels[0];
+
+        let fluid_size = self
+            .fluid_size
+            .clamp(*FLUID_SIZE.start(), *FLUID_SIZE.end());
+        let fluid_size =
+            (fluid_size + FLUID_SIZE_MULTIPLE / 2) / FLUID_SIZE_MULTIPLE * FLUID_SIZE_MULTIPLE;
+
+        let noise_channels = if self.noise_channels.is_empty() {
+            default.noise_channels.clone()
+        } else {
+            self.noise_channels
+                .iter()
+                .map(|channel| Noise {
+                    scale: clamp_f32(channel.scale, NOISE_SCALE, default_channel.scale),
+                    multiplier: clamp_f32(
+                        channel.multiplier,
+                        NOISE_CHANNEL_MULTIPLIER,
+                        default_channel.multiplier,
+                    ),
+                    offset_increment: clamp_f32(
+                        channel.offset_increment,
+                        NOISE_OFFSET_INCREMENT,
+                        default_channel.offset_increment,
+                    ),
+                })
+                .collect()
+        };
+
+        Settings {
+            version: self.version,
+            mode: self.mode.clone(),
+            seed: self.seed.clone(),
+            fluid_size,
+            fluid_frame_rate: clamp_f32(
+                self.fluid_frame_rate,
+                FLUID_FRAME_RATE,
+                default.fluid_frame_rate,
+            ),
+            fluid_timestep: clamp_f32(self.fluid_timestep, FLUID_TIMESTEP, default.fluid_timestep),
+            adaptive_timestep: self.adaptive_timestep.map(|adaptive| {
+                let default_adaptive = AdaptiveTimestep::default();
+                AdaptiveTimestep {
+                    cfl: clamp_f32(adaptive.cfl, CFL, default_adaptive.cfl),
+                    max_substeps: adaptive
+                        .max_substeps
+                        .clamp(*MAX_SUBSTEPS.start(), *MAX_SUBSTEPS.end()),
+                }
+            }),
+            viscosity: clamp_f32(self.viscosity, VISCOSITY, default.viscosity),
+            velocity_dissipation: clamp_f32(
+                self.velocity_dissipation,
+                VELOCITY_DISSIPATION,
+                default.velocity_dissipation,
+            ),
+            advection: self.advection,
+            boundary: self.boundary,
+            pressure_mode: match self.pressure_mode {
+                PressureMode::Retain => PressureMode::Retain,
+                PressureMode::ClearWith(pressure) => {
+                    PressureMode::ClearWith(clamp_f32(pressure, PRESSURE, 0.0))
+                }
+            },
+            diffusion_iterations: self
+                .diffusion_iterations
+                .clamp(*DIFFUSION_ITERATIONS.start(), *DIFFUSION_ITERATIONS.end()),
+            pressure_iterations: self
+                .pressure_iterations
+                .clamp(*PRESSURE_ITERATIONS.start(), *PRESSURE_ITERATIONS.end()),
+            pressure_solver: match self.pressure_solver {
+                PressureSolver::Jacobi => PressureSolver::Jacobi,
+                PressureSolver::Multigrid { cycles } => PressureSolver::Multigrid {
+                    cycles: cycles.clamp(*MULTIGRID_CYCLES.start(), *MULTIGRID_CYCLES.end()),
+                },
+            },
+            vorticity: clamp_f32(self.vorticity, VORTICITY, default.vorticity),
+            dye_dissipation: clamp_f32(
+                self.dye_dissipation,
+                DYE_DISSIPATION,
+                default.dye_dissipation,
+            ),
+            dye_emitters: self
+                .dye_emitters
+                .iter()
+                .take(MAX_DYE_EMITTERS)
+                .map(|emitter| DyeEmitter {
+                    position: emitter
+                        .position
+                        .map(|value| clamp_f32(value, DYE_EMITTER_POSITION, 0.5)),
+                    radius: clamp_f32(emitter.radius, DYE_EMITTER_RADIUS, 0.05),
+                    color: emitter
+                        .color
+                        .map(|value| clamp_f32(value, DYE_EMITTER_COLOR, 1.0)),
+                    rate: clamp_f32(emitter.rate, DYE_EMITTER_RATE, 1.0),
+                })
+                .collect(),
+            color_mode: match &self.color_mode {
+                // A gradient needs at least one stop.
+                ColorMode::Gradient(stops) if stops.is_empty() => default.color_mode.clone(),
+                ColorMode::Gradient(stops) => ColorMode::Gradient(
+                    stops
+                        .iter()
+                        .take(MAX_GRADIENT_STOPS)
+                        .map(|stop| GradientStop {
+                            color: stop
+                                .color
+                                .map(|value| clamp_f32(value, GRADIENT_COLOR, 1.0)),
+                            position: clamp_f32(stop.position, GRADIENT_POSITION, 0.0),
+                            interpolation: stop.interpolation,
+                        })
+                        .collect(),
+                ),
+                ColorMode::ImageFile { path, options } => ColorMode::ImageFile {
+                    path: path.clone(),
+                    options: clamp_image_options(options),
+                },
+                ColorMode::ImagePalette { path, options } => ColorMode::ImagePalette {
+                    path: path.clone(),
+                    options: clamp_image_options(options),
+                },
+                color_mode => color_mode.clone(),
+            },
+            gradient_color_space: self.gradient_color_space,
+            line_length: clamp_f32(self.line_length, LINE_LENGTH, default.line_length),
+            line_width: clamp_f32(self.line_width, LINE_WIDTH, default.line_width),
+            line_begin_offset: clamp_f32(
+                self.line_begin_offset,
+                LINE_BEGIN_OFFSET,
+                default.line_begin_offset,
+            ),
+            line_variance: clamp_f32(self.line_variance, LINE_VARIANCE, default.line_variance),
+            grid_spacing: self
+                .grid_spacing
+                .clamp(*GRID_SPACING.start(), *GRID_SPACING.end()),
+            view_scale: clamp_f32(self.view_scale, VIEW_SCALE, default.view_scale),
+            noise_multiplier: clamp_f32(
+                self.noise_multiplier,
+                NOISE_MULTIPLIER,
+                default.noise_multiplier,
+            ),
+            noise_channels,
+            obstacle_mask: ObstacleMask {
+                threshold: clamp_f32(
+                    self.obstacle_mask.threshold,
+                    OBSTACLE_THRESHOLD,
+                    default.obstacle_mask.threshold,
+                ),
+                ..self.obstacle_mask
+            },
+        }
+    }
+}
+
+fn check_u32(errors: &mut Vec<FieldError>, field: &str, value: u32, range: RangeInclusive<u32>) {
+    if !range.contains(&value) {
+        errors.push(FieldError {
+            field: field.to_string(),
+            message: format!("{} is outside {}..={}", value, range.start(), range.end()),
+        });
+    }
+}
+
+fn check_f32(errors: &mut Vec<FieldError>, field: &str, value: f32, range: RangeInclusive<f32>) {
+    // `contains` is false for NaN.
+    if !range.contains(&value) {
+        errors.push(FieldError {
+            field: field.to_string(),
+            message: format!("{} is outside {}..={}", value, range.start(), range.end()),
+        });
+    }
+}
+
+fn clamp_f32(value: f32, range: RangeInclusive<f32>, default: f32) -> f32 {
+    if value.is_nan() {
+        default
+    } else {
+        value.clamp(*range.start(), *range.end())
+    }
+}
+
+fn clamp_image_options(options: &ImageOptions) -> ImageOptions {
+    let default = ImageOptions::default();
+    ImageOptions {
+        width: options.width.clamp(*IMAGE_SIZE.start(), *IMAGE_SIZE.end()),
+        height: options.height.clamp(*IMAGE_SIZE.start(), *IMAGE_SIZE.end()),
+        black_level: clamp_f32(options.black_level, IMAGE_BLACK_LEVEL, default.black_level),
+        saturation: clamp_f32(options.saturation, IMAGE_SATURATION, default.saturation),
+        contrast: clamp_f32(options.contrast, IMAGE_CONTRAST, default.contrast),
+        gamma: clamp_f32(options.gamma, IMAGE_GAMMA, default.gamma),
+        blur: clamp_f32(options.blur, IMAGE_BLUR, default.blur),
+        ..*options
+    }
+}
+
 #[derive(Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub enum Mode {
     #[default]
     Normal,
+    /// Draws the dye, with the lines on top.
+    Dye,
     DebugNoise,
     DebugFluid,
     DebugPressure,
     DebugDivergence,
+    DebugVorticity,
+}
+
+/// How the velocity is carried along itself each step. Trades sharpness for
+/// GPU time.
+#[derive(Copy, Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Advection {
+    /// One backwards trace. The cheapest, but smooths out small swirls.
+    SemiLagrangian,
+    /// Adds a reverse trace to correct the first one's error, clamped so it
+    /// can't overshoot. Costs about three semi-Lagrangian passes.
+    #[default]
+    MacCormack,
+    /// Back and forth error compensation and correction: like MacCormack,
+    /// then advects the corrected velocity again. The sharpest, at about four
+    /// passes.
+    Bfecc,
+}
+
+/// How the fluid behaves at the edges of the domain.
+#[derive(Copy, Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Boundary {
+    /// Walls the fluid slides along but can't flow through.
+    #[default]
+    FreeSlip,
+    /// Walls the fluid sticks to, like the sides of a box.
+    NoSlip,
+    /// Opposite edges join up, so the fluid wraps around and the field tiles
+    /// seamlessly.
+    Periodic,
+}
+
+/// Limits how far the fluid can travel in one substep, in cells. Above that,
+/// advection starts to lose detail and can blow up.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AdaptiveTimestep {
+    /// The Courant number: the most cells the fastest fluid may cross per
+    /// substep.
+    pub cfl: f32,
+    /// The most substeps a fluid step is split into. Past this, the fluid
+    /// moves further than `cfl` allows.
+    pub max_substeps: u32,
+}
+
+impl Default for AdaptiveTimestep {
+    fn default() -> Self {
+        Self {
+            cfl: 1.0,
+            max_substeps: 8,
+        }
+    }
+}
+
+impl From<Boundary> for u32 {
+    fn from(val: Boundary) -> Self {
+        match val {
+            Boundary::FreeSlip => 0,
+            Boundary::NoSlip => 1,
+            Boundary::Periodic => 2,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -92,10 +750,65 @@ impl Default for PressureMode {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub enum PressureSolver {
+    /// Runs `pressure_iterations` Jacobi sweeps.
+    #[default]
+    Jacobi,
+    /// Runs `cycles` multigrid V-cycles. Each costs a few Jacobi sweeps, but
+    /// converges far faster at large fluid sizes. `pressure_iterations` is
+    /// ignored.
+    Multigrid { cycles: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ColorMode {
     Preset(ColorPreset),
-    ImageFile(std::path::PathBuf),
+    /// Sample colors from an image, by the direction and speed of the fluid.
+    ///
+    /// `Flux::new` and `Flux::update` read the file, and fail if they can't.
+    /// On the web, the host loads the image instead.
+    ImageFile {
+        path: std::path::PathBuf,
+        /// How the image is prepared before it's sampled.
+        #[serde(default)]
+        options: ImageOptions,
+    },
+    /// Color each line by its direction, from the dominant colors of an
+    /// image spread around a color wheel, rather than from where the colors
+    /// are in the image. See `render::color::extract_palette`.
+    ///
+    /// Loaded like `ImageFile`. On the web, the host loads the image and
+    /// hands it to `Flux::sample_palette_from_image`.
+    ImagePalette {
+        path: std::path::PathBuf,
+        #[serde(default)]
+        options: ImageOptions,
+    },
+    /// Color each line with the dye at its basepoint.
+    Dye,
+    /// Color each line by its direction, picked from a gradient that wraps
+    /// once around the compass. The stops can be in any order.
+    Gradient(Vec<GradientStop>),
+}
+
+impl ColorMode {
+    /// The gradient the lines are colored from by direction, if any. The
+    /// color wheel presets are gradients with evenly spaced stops.
+    pub fn gradient(&self) -> Option<Vec<GradientStop>> {
+        match self {
+            ColorMode::Preset(preset) => preset.to_color_wheel().map(|color_wheel| {
+                let colors = color_wheel
+                    .chunks_exact(4)
+                    .map(|color| [color[0], color[1], color[2], color[3]])
+                    .collect::<Vec<_>>();
+                GradientStop::evenly_spaced(&colors)
+            }),
+            ColorMode::Gradient(stops) => Some(stops.clone()),
+            // The palette depends on the image, so `Flux` picks it.
+            ColorMode::ImageFile { .. } | ColorMode::ImagePalette { .. } | ColorMode::Dye => None,
+        }
+    }
 }
 
 impl Default for ColorMode {
@@ -108,8 +821,9 @@ impl From<ColorMode> for u32 {
     fn from(val: ColorMode) -> Self {
         match val {
             ColorMode::Preset(ColorPreset::Original) => 0,
-            ColorMode::Preset(_) => 1,
-            ColorMode::ImageFile(_) => 2,
+            ColorMode::Preset(_) | ColorMode::Gradient(_) | ColorMode::ImagePalette { .. } => 1,
+            ColorMode::ImageFile { .. } => 2,
+            ColorMode::Dye => 3,
         }
     }
 }
@@ -133,6 +847,168 @@ impl ColorPreset {
     }
 }
 
+/// How a `ColorMode::ImageFile` or `ColorMode::ImagePalette` image is
+/// prepared before colors are taken from it. The steps run in the order of
+/// the fields.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ImageOptions {
+    /// The size, in pixels, the image is fitted to with `fit`.
+    pub width: u32,
+    pub height: u32,
+    pub fit: ImageFitMode,
+    pub filter: ResampleFilter,
+    /// The standard deviation of a Gaussian blur, in pixels of the fitted
+    /// image. Zero turns it off. Blurring evens out fine detail, like text or noise.
+    pub blur: f32,
+    /// Scales the distance of each channel from mid-grey.
+    pub contrast: f32,
+    /// Scales the distance of each color from the grey of the same
+    /// luminance. Zero turns the image grey.
+    pub saturation: f32,
+    /// Above 1, brightens the midtones; below 1, darkens them.
+    pub gamma: f32,
+    /// Pixels darker than this in every channel, from 0 to 1, are lifted to
+    /// it, so large dark areas don't turn the lines black.
+    pub black_level: f32,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 400,
+            fit: ImageFitMode::Contain,
+            filter: ResampleFilter::Nearest,
+            blur: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+            black_level: 25.0 / 255.0,
+        }
+    }
+}
+
+/// How an image is fitted to the size in `ImageOptions`.
+#[derive(Copy, Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum ImageFitMode {
+    /// Shrinks the image to fit inside the size, keeping its aspect ratio.
+    /// Smaller images are left as they are.
+    #[default]
+    Contain,
+    /// Scales the image to cover the size, keeping its aspect ratio, and
+    /// crops the overflow from the centre.
+    Cover,
+    /// Scales the image to the size, ignoring its aspect ratio.
+    Stretch,
+}
+
+/// The filter used to resize an image, from fastest to smoothest.
+#[derive(Copy, Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum ResampleFilter {
+    #[default]
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+/// The most stops a `ColorMode::Gradient` can have.
+pub const MAX_GRADIENT_STOPS: usize = 64;
+
+/// A color along a `ColorMode::Gradient`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GradientStop {
+    /// RGBA, each from 0 to 1, in the same space as the color presets.
+    pub color: [f32; 4],
+    /// Where the stop sits along the gradient, from 0 to 1. The gradient
+    /// wraps around, so 0 and 1 are the same place.
+    pub position: f32,
+    /// How the color changes on the way to the next stop.
+    #[serde(default)]
+    pub interpolation: GradientInterpolation,
+}
+
+impl GradientStop {
+    /// A gradient that blends linearly through `colors` in order, with the
+    /// stops spread evenly around it.
+    pub fn evenly_spaced(colors: &[[f32; 4]]) -> Vec<GradientStop> {
+        colors
+            .iter()
+            .enumerate()
+            .map(|(i, &color)| GradientStop {
+                color,
+                position: i as f32 / colors.len() as f32,
+                interpolation: GradientInterpolation::Linear,
+            })
+            .collect()
+    }
+}
+
+/// How a gradient blends from one stop to the next.
+#[derive(Copy, Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum GradientInterpolation {
+    #[default]
+    Linear,
+    /// Eases in and out of each stop.
+    Smooth,
+    /// Holds the stop's color up to the next stop, for hard bands.
+    Constant,
+}
+
+impl From<GradientInterpolation> for u32 {
+    fn from(val: GradientInterpolation) -> Self {
+        match val {
+            GradientInterpolation::Linear => 0,
+            GradientInterpolation::Smooth => 1,
+            GradientInterpolation::Constant => 2,
+        }
+    }
+}
+
+/// The color space a gradient blends between its stops in.
+#[derive(Copy, Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum ColorSpace {
+    /// Blends the sRGB values directly. Stops of very different hues meet
+    /// at a dull, darker midpoint.
+    #[default]
+    Srgb,
+    /// Blends in the perceptual OKLab space, which keeps the lightness even.
+    Oklab,
+    /// Blends lightness, chroma and hue, taking the short way around the hue
+    /// circle, which keeps the midpoints as vivid as the stops.
+    Oklch,
+}
+
+impl From<ColorSpace> for u32 {
+    fn from(val: ColorSpace) -> Self {
+        match val {
+            ColorSpace::Srgb => 0,
+            ColorSpace::Oklab => 1,
+            ColorSpace::Oklch => 2,
+        }
+    }
+}
+
+/// The most dye emitters the fluid can handle.
+pub const MAX_DYE_EMITTERS: usize = 16;
+
+/// A source that continuously adds dye around a point.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DyeEmitter {
+    /// The centre, as a fraction of the view from its top-left corner.
+    pub position: [f32; 2],
+    /// The radius, as a fraction of the view height.
+    pub radius: f32,
+    /// An sRGB color with straight alpha.
+    pub color: [f32; 4],
+    /// How quickly the dye at the centre turns to `color`, per second.
+    pub rate: f32,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Noise {
@@ -141,6 +1017,37 @@ pub struct Noise {
     pub offset_increment: f32,
 }
 
+/// Which part of the mask image marks a cell as solid.
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum MaskChannel {
+    /// Opaque pixels are solid. Suits logos with a transparent background.
+    #[default]
+    Alpha,
+    /// Bright pixels are solid. Use `invert` for dark shapes on a light
+    /// background.
+    Luminance,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ObstacleMask {
+    pub channel: MaskChannel,
+    /// Pixels whose channel value, from 0 to 1, is above this are solid.
+    pub threshold: f32,
+    /// Swap solid and open pixels. The area around the image stays open.
+    pub invert: bool,
+}
+
+impl Default for ObstacleMask {
+    fn default() -> Self {
+        Self {
+            channel: MaskChannel::Alpha,
+            threshold: 0.5,
+            invert: false,
+        }
+    }
+}
+
 #[rustfmt::skip]
 pub static COLOR_SCHEME_PLASMA: [f32; 24] = [
     60.219  / 255.0, 37.2487 / 255.0, 66.4301 / 255.0, 1.0,
@@ -160,3 +1067,321 @@ pub static COLOR_SCHEME_POOLSIDE: [f32; 24] = [
     124.0 / 255.0, 220.0 / 255.0, 236.0 / 255.0, 1.0,
     156.0 / 255.0, 208.0 / 255.0, 236.0 / 255.0, 1.0,
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fields(settings: &Settings) -> Vec<String> {
+        settings
+            .validate()
+            .unwrap_err()
+            .into_iter()
+            .map(|err| err.field)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_the_defaults() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn reports_every_invalid_field() {
+        let settings = Settings {
+            fluid_size: 100,
+            fluid_frame_rate: -60.0,
+            viscosity: 0.0,
+            grid_spacing: 0,
+            noise_channels: vec![Noise {
+                scale: f32::NAN,
+                multiplier: 1.0,
+                offset_increment: 0.001,
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            fields(&settings),
+            vec![
+                "fluidSize",
+                "fluidFrameRate",
+                "viscosity",
+                "gridSpacing",
+                "noiseChannels[0].scale",
+            ]
+        );
+    }
+
+    #[test]
+    fn requires_a_noise_channel() {
+        let settings = Settings {
+            noise_channels: Vec::new(),
+            ..Default::default()
+        };
+        assert_eq!(fields(&settings), vec!["noiseChannels"]);
+    }
+
+    #[test]
+    fn clamps_into_valid_ranges() {
+        let settings = Settings {
+            fluid_size: 100,
+            fluid_frame_rate: -60.0,
+            fluid_timestep: f32::NAN,
+            adaptive_timestep: Some(AdaptiveTimestep {
+                cfl: 0.0,
+                max_substeps: 100,
+            }),
+            viscosity: 0.0,
+            pressure_mode: PressureMode::ClearWith(f32::INFINITY),
+            pressure_solver: PressureSolver::Multigrid { cycles: 0 },
+            grid_spacing: 0,
+            noise_channels: Vec::new(),
+            ..Default::default()
+        };
+
+        let clamped = settings.clamped();
+        assert_eq!(clamped.validate(), Ok(()));
+        assert_eq!(clamped.fluid_size, 96);
+        assert_eq!(clamped.fluid_frame_rate, 1.0);
+        assert_eq!(clamped.fluid_timestep, Settings::default().fluid_timestep);
+        assert_eq!(
+            clamped.adaptive_timestep,
+            Some(AdaptiveTimestep {
+                cfl: 0.1,
+                max_substeps: 16,
+            })
+        );
+        assert_eq!(clamped.viscosity, 0.001);
+        assert_eq!(clamped.pressure_mode, PressureMode::ClearWith(1000.0));
+        assert_eq!(
+            clamped.pressure_solver,
+            PressureSolver::Multigrid { cycles: 1 }
+        );
+        assert_eq!(clamped.grid_spacing, 4);
+        assert_eq!(clamped.noise_channels, Settings::default().noise_channels);
+    }
+
+    #[test]
+    fn limits_dye_emitters() {
+        let emitter = DyeEmitter {
+            position: [0.5, 1.5],
+            radius: 0.1,
+            color: [1.0, 0.5, 0.0, 1.0],
+            rate: 2.0,
+        };
+        let settings = Settings {
+            dye_emitters: vec![emitter; MAX_DYE_EMITTERS + 1],
+            ..Default::default()
+        };
+        let fields = fields(&settings);
+        assert_eq!(fields[0], "dyeEmitters");
+        assert_eq!(fields[1], "dyeEmitters[0].position[1]");
+
+        let clamped = settings.clamped();
+        assert_eq!(clamped.validate(), Ok(()));
+        assert_eq!(clamped.dye_emitters.len(), MAX_DYE_EMITTERS);
+        assert_eq!(clamped.dye_emitters[0].position, [0.5, 1.0]);
+    }
+
+    #[test]
+    fn checks_gradient_stops() {
+        let stop = GradientStop {
+            color: [1.0, 0.5, 0.0, 1.0],
+            position: 0.5,
+            interpolation: GradientInterpolation::Linear,
+        };
+        let settings = Settings {
+            color_mode: ColorMode::Gradient(vec![
+                stop.clone(),
+                GradientStop {
+                    color: [2.0, 0.0, 0.0, 1.0],
+                    position: -0.5,
+                    ..stop.clone()
+                },
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            fields(&settings),
+            vec![
+                "colorMode.Gradient[1].color[0]",
+                "colorMode.Gradient[1].position",
+            ]
+        );
+
+        let clamped = settings.clamped();
+        assert_eq!(clamped.validate(), Ok(()));
+        assert_eq!(
+            clamped.color_mode.gradient().unwrap()[1],
+            GradientStop {
+                color: [1.0, 0.0, 0.0, 1.0],
+                position: 0.0,
+                ..stop
+            }
+        );
+
+        let empty = Settings {
+            color_mode: ColorMode::Gradient(Vec::new()),
+            ..Default::default()
+        };
+        assert_eq!(fields(&empty), vec!["colorMode.Gradient"]);
+        assert_eq!(empty.clamped().color_mode, ColorMode::default());
+    }
+
+    #[test]
+    fn checks_image_options() {
+        let settings = Settings {
+            color_mode: ColorMode::ImageFile {
+                path: "image.png".into(),
+                options: ImageOptions {
+                    width: 0,
+                    gamma: 0.0,
+                    blur: f32::NAN,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            fields(&settings),
+            vec![
+                "colorMode.ImageFile.options.width",
+                "colorMode.ImageFile.options.gamma",
+                "colorMode.ImageFile.options.blur",
+            ]
+        );
+
+        let clamped = settings.clamped();
+        assert_eq!(clamped.validate(), Ok(()));
+        let ColorMode::ImageFile { options, .. } = clamped.color_mode else {
+            panic!("expected an image file");
+        };
+        assert_eq!((options.width, options.gamma, options.blur), (1, 0.1, 0.0));
+
+        let palette = Settings {
+            color_mode: ColorMode::ImagePalette {
+                path: "image.png".into(),
+                options: ImageOptions {
+                    saturation: -1.0,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            fields(&palette),
+            vec!["colorMode.ImagePalette.options.saturation"]
+        );
+        let ColorMode::ImagePalette { options, .. } = palette.clamped().color_mode else {
+            panic!("expected an image palette");
+        };
+        assert_eq!(options.saturation, 0.0);
+        // Colored by direction, from the palette.
+        assert_eq!(u32::from(palette.color_mode), 1);
+    }
+
+    #[test]
+    fn spreads_color_wheels_evenly() {
+        let stops = ColorMode::Preset(ColorPreset::Plasma).gradient().unwrap();
+        assert_eq!(stops.len(), 6);
+        assert_eq!(stops[3].position, 0.5);
+        assert_eq!(stops[3].color, COLOR_SCHEME_PLASMA[12..16]);
+        assert_eq!(ColorMode::Preset(ColorPreset::Original).gradient(), None);
+    }
+
+    #[test]
+    fn migrates_unversioned_gl_settings() {
+        let settings =
+            Settings::from_json(include_str!("../fixtures/settings/unversioned-gl.json")).unwrap();
+        assert_eq!(settings.version, VERSION);
+        assert_eq!(settings.pressure_mode, PressureMode::Retain);
+        assert_eq!(settings.line_length, 550.0);
+        assert_eq!(settings.noise_channels[0].offset_increment, 0.0015);
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn migrates_unversioned_settings() {
+        let settings =
+            Settings::from_json(include_str!("../fixtures/settings/unversioned.json")).unwrap();
+        assert_eq!(settings.version, VERSION);
+        assert_eq!(settings.noise_multiplier, 0.3);
+        assert_eq!(settings.pressure_mode, PressureMode::ClearWith(0.0));
+        assert_eq!(
+            settings.color_mode,
+            ColorMode::ImageFile {
+                path: "colors/gumdrop.png".into(),
+                options: ImageOptions::default(),
+            }
+        );
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn migrates_v1_settings() {
+        let settings = Settings::from_json(include_str!("../fixtures/settings/v1.json")).unwrap();
+        assert_eq!(settings.version, VERSION);
+        assert_eq!(settings.color_mode, ColorMode::Preset(ColorPreset::Plasma));
+        assert_eq!(settings.validate(), Ok(()));
+
+        let settings =
+            Settings::from_json(r#"{ "version": 1, "colorMode": { "ImageFile": "a.png" } }"#)
+                .unwrap();
+        assert_eq!(
+            settings.color_mode,
+            ColorMode::ImageFile {
+                path: "a.png".into(),
+                options: ImageOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_current_settings() {
+        let settings = Settings::from_json(include_str!("../fixtures/settings/v2.json")).unwrap();
+        assert_eq!(settings.version, 2);
+        assert_eq!(
+            settings.color_mode,
+            ColorMode::ImageFile {
+                path: "colors/silver.png".into(),
+                options: ImageOptions {
+                    fit: ImageFitMode::Cover,
+                    saturation: 1.4,
+                    black_level: 0.2,
+                    ..Default::default()
+                },
+            }
+        );
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let json = serde_json::to_string(&Settings::default()).unwrap();
+        let settings = Settings::from_json(&json).unwrap();
+        assert_eq!(settings.version, VERSION);
+        assert_eq!(settings.noise_channels, Settings::default().noise_channels);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let json = format!(r#"{{ "version": {} }}"#, VERSION + 1);
+        assert!(matches!(
+            Settings::from_json(&json),
+            Err(Error::UnsupportedSettingsVersion(_))
+        ));
+        assert!(Settings::from_json(r#"{ "version": 0 }"#).is_err());
+        assert!(Settings::from_json(r#"{ "version": "one" }"#).is_err());
+        assert!(Settings::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn leaves_valid_settings_unchanged() {
+        let settings = Settings::default();
+        let clamped = settings.clamped();
+        assert_eq!(clamped.fluid_size, settings.fluid_size);
+        assert_eq!(clamped.viscosity, settings.viscosity);
+        assert_eq!(clamped.noise_channels, settings.noise_channels);
+    }
+}
@@ -0,0 +1,399 @@
+// The complete simulation state of a running Flux instance.
+//
+// A snapshot is read back from the GPU with `Flux::snapshot` and written back
+// with `Flux::restore`. It can be stored with any serde format, or with the
+// compact versioned binary format implemented here, which is what we use for
+// state files.
+//
+// Settings aren't part of a snapshot. Restore into an instance created with
+// the same settings and size.
+
+use crate::error::{Error, Result};
+
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 4] = b"FLUX";
+
+/// The current version of the binary format. Bump this whenever the layout
+/// written by `Snapshot::write_to` changes.
+pub const VERSION: u32 = 4;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub elapsed_time: f32,
+    pub fluid_frame_time: f32,
+    // The substeps picked for the last fluid step.
+    pub substeps: u32,
+    pub fluid: FluidState,
+    pub lines: LinesState,
+    pub noise: NoiseState,
+    pub rng: RngState,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FluidState {
+    pub width: u32,
+    pub height: u32,
+    // Interleaved (x, y) per texel, row by row.
+    pub velocity: Vec<f32>,
+    pub pressure: Vec<f32>,
+    // Linear, premultiplied RGBA per texel, row by row.
+    pub dye: Vec<f32>,
+    // The velocity from before the last fluid step, which the lines blend
+    // towards the current one. Laid out like `velocity`.
+    pub previous_velocity: Vec<f32>,
+    pub speed: SpeedState,
+}
+
+// The fluid speed measurements that pick the substeps and spot blow-ups.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedState {
+    // The latest measurement read back.
+    pub max_speed: Option<f32>,
+    // A measurement still being read back.
+    pub pending: Option<PendingSpeed>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingSpeed {
+    // `None` if the field changed after it was taken, so it'll be dropped.
+    pub max_speed: Option<f32>,
+    // How many more frames until it lands, either 1 or 2.
+    pub frames_left: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinesState {
+    pub columns: u32,
+    pub rows: u32,
+    pub noise_offset_1: f32,
+    pub noise_offset_2: f32,
+    pub noise_blend_factor: f32,
+    // The raw `Line` structs, as laid out on the GPU.
+    pub lines: Vec<f32>,
+    // Interleaved (x, y) per line.
+    pub basepoints: Vec<f32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseState {
+    pub elapsed_time: f32,
+    pub channels: Vec<NoiseChannelState>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseChannelState {
+    pub offset_1: f32,
+    pub offset_2: f32,
+    pub blend_factor: f32,
+}
+
+// The position of the instance's random number generator in its stream, so
+// that random choices made after a restore match those of the original.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RngState {
+    pub state: u64,
+    pub stream: u64,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec can't fail");
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+        Self::read_from(&mut bytes)
+    }
+
+    /// Writes the snapshot in the versioned binary format. All values are
+    /// little-endian.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+
+        write_f32(writer, self.elapsed_time)?;
+        write_f32(writer, self.fluid_frame_time)?;
+        write_u32(writer, self.substeps)?;
+
+        let fluid = &self.fluid;
+        write_u32(writer, fluid.width)?;
+        write_u32(writer, fluid.height)?;
+        write_f32s(writer, &fluid.velocity)?;
+        write_f32s(writer, &fluid.pressure)?;
+        write_f32s(writer, &fluid.dye)?;
+        write_f32s(writer, &fluid.previous_velocity)?;
+        write_optional_f32(writer, fluid.speed.max_speed)?;
+        match fluid.speed.pending {
+            None => write_u32(writer, 0)?,
+            Some(pending) => {
+                write_u32(writer, pending.frames_left)?;
+                write_optional_f32(writer, pending.max_speed)?;
+            }
+        }
+
+        let lines = &self.lines;
+        write_u32(writer, lines.columns)?;
+        write_u32(writer, lines.rows)?;
+        write_f32(writer, lines.noise_offset_1)?;
+        write_f32(writer, lines.noise_offset_2)?;
+        write_f32(writer, lines.noise_blend_factor)?;
+        write_f32s(writer, &lines.lines)?;
+        write_f32s(writer, &lines.basepoints)?;
+
+        let noise = &self.noise;
+        write_f32(writer, noise.elapsed_time)?;
+        write_u32(writer, noise.channels.len() as u32)?;
+        for channel in noise.channels.iter() {
+            write_f32(writer, channel.offset_1)?;
+            write_f32(writer, channel.offset_2)?;
+            write_f32(writer, channel.blend_factor)?;
+        }
+
+        write_u64(writer, self.rng.state)?;
+        write_u64(writer, self.rng.stream)?;
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|err| Error::Snapshot(err.to_string()))?;
+        if &magic != MAGIC {
+            return Err(Error::Snapshot("not a Flux snapshot".to_string()));
+        }
+
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(Error::Snapshot(format!(
+                "unsupported version {} (expected {})",
+                version, VERSION
+            )));
+        }
+
+        let elapsed_time = read_f32(reader)?;
+        let fluid_frame_time = read_f32(reader)?;
+        let substeps = read_u32(reader)?;
+
+        let fluid = FluidState {
+            width: read_u32(reader)?,
+            height: read_u32(reader)?,
+            velocity: read_f32s(reader)?,
+            pressure: read_f32s(reader)?,
+            dye: read_f32s(reader)?,
+            previous_velocity: read_f32s(reader)?,
+            speed: SpeedState {
+                max_speed: read_optional_f32(reader)?,
+                pending: match read_u32(reader)? {
+                    0 => None,
+                    frames_left => Some(PendingSpeed {
+                        max_speed: read_optional_f32(reader)?,
+                        frames_left,
+                    }),
+                },
+            },
+        };
+
+        let lines = LinesState {
+            columns: read_u32(reader)?,
+            rows: read_u32(reader)?,
+            noise_offset_1: read_f32(reader)?,
+            noise_offset_2: read_f32(reader)?,
+            noise_blend_factor: read_f32(reader)?,
+            lines: read_f32s(reader)?,
+            basepoints: read_f32s(reader)?,
+        };
+
+        let noise_elapsed_time = read_f32(reader)?;
+        let channel_count = read_u32(reader)?;
+        let channels = (0..channel_count)
+            .map(|_| {
+                Ok(NoiseChannelState {
+                    offset_1: read_f32(reader)?,
+                    offset_2: read_f32(reader)?,
+                    blend_factor: read_f32(reader)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let rng = RngState {
+            state: read_u64(reader)?,
+            stream: read_u64(reader)?,
+        };
+
+        Ok(Snapshot {
+            elapsed_time,
+            fluid_frame_time,
+            substeps,
+            fluid,
+            lines,
+            noise: NoiseState {
+                elapsed_time: noise_elapsed_time,
+                channels,
+            },
+            rng,
+        })
+    }
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> std::io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> std::io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_f32<W: Write>(writer: &mut W, value: f32) -> std::io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+// A presence flag, followed by the value if there is one.
+fn write_optional_f32<W: Write>(writer: &mut W, value: Option<f32>) -> std::io::Result<()> {
+    match value {
+        None => write_u32(writer, 0),
+        Some(value) => {
+            write_u32(writer, 1)?;
+            write_f32(writer, value)
+        }
+    }
+}
+
+// A length-prefixed array.
+fn write_f32s<W: Write>(writer: &mut W, values: &[f32]) -> std::io::Result<()> {
+    write_u32(writer, values.len() as u32)?;
+    for value in values {
+        write_f32(writer, *value)?;
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|err| Error::Snapshot(err.to_string()))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|err| Error::Snapshot(err.to_string()))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> Result<f32> {
+    read_u32(reader).map(f32::from_bits)
+}
+
+fn read_optional_f32<R: Read>(reader: &mut R) -> Result<Option<f32>> {
+    match read_u32(reader)? {
+        0 => Ok(None),
+        1 => read_f32(reader).map(Some),
+        flag => Err(Error::Snapshot(format!("invalid presence flag {}", flag))),
+    }
+}
+
+fn read_f32s<R: Read>(reader: &mut R) -> Result<Vec<f32>> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = Vec::new();
+    reader
+        .take(4 * len as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|err| Error::Snapshot(err.to_string()))?;
+    if bytes.len() != 4 * len {
+        return Err(Error::Snapshot("truncated".to_string()));
+    }
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_snapshot() -> Snapshot {
+        Snapshot {
+            elapsed_time: 12.5,
+            fluid_frame_time: 0.004,
+            substeps: 3,
+            fluid: FluidState {
+                width: 2,
+                height: 1,
+                velocity: vec![0.1, -0.2, f32::MIN_POSITIVE, 3.0],
+                pressure: vec![0.5, -0.5],
+                dye: vec![0.25, 0.0, 0.125, 0.5, 0.0, 0.0, 0.0, 0.0],
+                previous_velocity: vec![0.0, -0.25, 1.0, 2.0],
+                speed: SpeedState {
+                    max_speed: Some(4.5),
+                    pending: Some(PendingSpeed {
+                        max_speed: None,
+                        frames_left: 2,
+                    }),
+                },
+            },
+            lines: LinesState {
+                columns: 1,
+                rows: 1,
+                noise_offset_1: 1.0,
+                noise_offset_2: 0.25,
+                noise_blend_factor: 0.125,
+                lines: (0..12).map(|i| i as f32).collect(),
+                basepoints: vec![0.5, 0.5],
+            },
+            noise: NoiseState {
+                elapsed_time: 3.0,
+                channels: vec![NoiseChannelState {
+                    offset_1: 999.0,
+                    offset_2: 1.0,
+                    blend_factor: 0.5,
+                }],
+            },
+            rng: RngState {
+                state: 0xcafe_f00d_d15e_a5e5,
+                stream: u64::MAX >> 1,
+            },
+        }
+    }
+
+    #[test]
+    fn round_trips_through_binary_format() {
+        let snapshot = test_snapshot();
+        assert_eq!(
+            Snapshot::from_bytes(&snapshot.to_bytes()).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = test_snapshot().to_bytes();
+        bytes[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(Snapshot::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_snapshots() {
+        let bytes = test_snapshot().to_bytes();
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Snapshot::from_bytes(b"NOPE").is_err());
+    }
+}
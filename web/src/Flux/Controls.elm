@@ -302,6 +302,7 @@ debug settings =
                     , debugOption settings.mode "fluid" "Fluid" DebugFluid
                     , debugOption settings.mode "pressure" "Pressure" DebugPressure
                     , debugOption settings.mode "divergence" "Divergence" DebugDivergence
+                    , debugOption settings.mode "vorticity" "Vorticity" DebugVorticity
                     ]
                 }
             ]
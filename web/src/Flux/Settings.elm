@@ -45,6 +45,7 @@ type Mode
     | DebugFluid
     | DebugPressure
     | DebugDivergence
+    | DebugVorticity
 
 
 type PressureMode
@@ -200,13 +201,22 @@ updateNoiseChannel msg noise =
             { noise | offsetIncrement = newOffsetIncrement }
 
 
+{-| The version of the settings format this module encodes. Keep in sync with
+`flux::settings::VERSION`.
+-}
+settingsVersion : Int
+settingsVersion =
+    2
+
+
 {-| Encode settings using the externally tagged enum representation expected by
 Serde.
 -}
 encode : Settings -> Encode.Value
 encode settings =
     Encode.object
-        [ ( "mode", encodeMode settings.mode )
+        [ ( "version", Encode.int settingsVersion )
+        , ( "mode", encodeMode settings.mode )
         , ( "seed", settings.seed |> Maybe.map Encode.string |> Maybe.withDefault Encode.null )
         , ( "fluidSize", Encode.int settings.fluidSize )
         , ( "fluidFrameRate", Encode.int settings.fluidFrameRate )
@@ -247,6 +257,9 @@ encodeMode mode =
             DebugDivergence ->
                 "DebugDivergence"
 
+            DebugVorticity ->
+                "DebugVorticity"
+
 
 encodePressureMode : PressureMode -> Encode.Value
 encodePressureMode pressureMode =
@@ -268,7 +281,7 @@ encodeColorMode colorMode =
 
         ImageFile path ->
             Encode.object
-                [ ( "ImageFile", Encode.string path )
+                [ ( "ImageFile", Encode.object [ ( "path", Encode.string path ) ] )
                 ]
 
 
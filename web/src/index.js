@@ -28,7 +28,23 @@ function setupFlux() {
       // while coalescing multiple observations into a single resize.
       pendingResize = entry.contentRect;
     });
-    resizeObserver.observe(document.getElementById("canvas"));
+    const canvas = document.getElementById("canvas");
+    resizeObserver.observe(canvas);
+
+    // Stir the fluid by dragging. Only the WebGPU backend supports this.
+    if (flux.pointer_down) {
+      canvas.style.touchAction = "none";
+      canvas.addEventListener("pointerdown", (event) => {
+        canvas.setPointerCapture(event.pointerId);
+        flux.pointer_down(event.pointerId, event.offsetX, event.offsetY, event.timeStamp);
+      });
+      canvas.addEventListener("pointermove", (event) => {
+        flux.pointer_move(event.pointerId, event.offsetX, event.offsetY, event.timeStamp);
+      });
+      for (const type of ["pointerup", "pointercancel"]) {
+        canvas.addEventListener(type, (event) => flux.pointer_up(event.pointerId));
+      }
+    }
 
     function animate(timestamp) {
       if (pendingResize) {
@@ -46,7 +62,7 @@ function setupFlux() {
   // Update settings
   ui.ports.setSettings.subscribe(async function(newSettings) {
     if (newSettings.colorMode?.ImageFile) {
-      loadImage(newSettings.colorMode.ImageFile)
+      loadImage(newSettings.colorMode.ImageFile.path)
         .then(bitmap => flux.save_image(bitmap));
     }
 